@@ -1,5 +1,7 @@
-use crate::record_types::{Chunk, IndexData};
-use crate::{record::Record, Cursor, Error, Result};
+use crate::record::{check_record_at, skip_unknown_record, Record};
+use crate::record_types::{Chunk, IndexData, RecordGen};
+use crate::{Cursor, Error, Result};
+use std::iter::FusedIterator;
 
 /// Record types which can be stored in the chunk section.
 #[derive(Debug, Clone)]
@@ -11,9 +13,15 @@ pub enum ChunkRecord<'a> {
 }
 
 /// Iterator over records stored in the chunk section of a rosbag file.
+///
+/// Iteration stops after the first error, but it can be resumed with `seek`.
 pub struct ChunkRecordsIterator<'a> {
     pub(crate) cursor: Cursor<'a>,
     pub(crate) offset: u64,
+    /// Iteration stops at the first record which starts at or after this
+    /// position
+    pub(crate) end: u64,
+    pub(crate) skip_unknown: bool,
 }
 
 impl<'a> ChunkRecordsIterator<'a> {
@@ -30,21 +38,156 @@ impl<'a> ChunkRecordsIterator<'a> {
         }
         Ok(self.cursor.seek(pos - self.offset)?)
     }
+
+    /// Jump to the given position in the file after checking that it points
+    /// to a `Chunk` or `IndexData` record.
+    ///
+    /// Lengths of the record header and data are checked against the file
+    /// size and [`OpenOptions::max_record_size`][crate::OpenOptions::max_record_size].
+    /// On error the iterator position is left unchanged. Useful for
+    /// positions taken from sources other than the bag file itself.
+    pub fn seek_validated(&mut self, pos: u64) -> Result<()> {
+        if pos < self.offset {
+            return Err(Error::OutOfBounds);
+        }
+        let pos = pos - self.offset;
+        let ops = [Chunk::OP, IndexData::OP];
+        check_record_at(&self.cursor, pos, &ops, Error::UnexpectedChunkSectionRecord)?;
+        Ok(self.cursor.seek(pos)?)
+    }
+
+    /// Skip records with unknown `op` values instead of returning an error.
+    ///
+    /// Skipped records are reported using `log::warn!`. Useful for reading
+    /// files which contain vendor-specific record types. Legacy message
+    /// definition records (`op=0x01`) are always skipped. Default: `false`.
+    pub fn skip_unknown(mut self, skip: bool) -> Self {
+        self.skip_unknown = skip;
+        self
+    }
+
+    /// Get current position in the file.
+    ///
+    /// Returned value can be later passed to [`seek`][Self::seek] to resume
+    /// iteration from the same record.
+    pub fn position(&self) -> u64 {
+        self.offset + self.cursor.pos()
+    }
+
+    /// Group each chunk with `IndexData` records which follow it.
+    ///
+    /// In the standard layout every chunk is followed by index records of
+    /// connections with messages in the chunk. `IndexData` records which
+    /// precede the first chunk are skipped with a warning. If a record fails
+    /// to parse, the chunk preceding it is yielded with the index records
+    /// read so far, followed by the error.
+    pub fn with_following_index(self) -> ChunksWithIndex<'a> {
+        ChunksWithIndex {
+            inner: self,
+            pending: None,
+            error: None,
+        }
+    }
 }
 
 impl<'a> Iterator for ChunkRecordsIterator<'a> {
     type Item = Result<ChunkRecord<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cursor.left() == 0 {
-            return None;
+        loop {
+            if self.cursor.left() == 0 || Self::position(self) >= self.end {
+                return None;
+            }
+            let res = match Record::next_record(&mut self.cursor) {
+                Err(Error::UnknownRecord(op)) if skip_unknown_record(op, self.skip_unknown) => {
+                    continue
+                }
+                Ok(Record::Chunk(v)) => Ok(ChunkRecord::Chunk(v)),
+                Ok(Record::IndexData(v)) => Ok(ChunkRecord::IndexData(v)),
+                Ok(v) => Err(Error::UnexpectedChunkSectionRecord(v.kind())),
+                Err(e) => Err(e),
+            };
+            if res.is_err() {
+                self.cursor.finish();
+            }
+            return Some(res);
+        }
+    }
+}
+
+/// Iterator adapter which groups chunks with their index records.
+///
+/// Created by [`ChunkRecordsIterator::with_following_index`].
+pub struct ChunksWithIndex<'a> {
+    inner: ChunkRecordsIterator<'a>,
+    pending: Option<(Chunk<'a>, Vec<IndexData<'a>>)>,
+    /// Error which follows the pending chunk, returned after the chunk
+    error: Option<Error>,
+}
+
+impl<'a> Iterator for ChunksWithIndex<'a> {
+    type Item = Result<(Chunk<'a>, Vec<IndexData<'a>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        loop {
+            match self.inner.next() {
+                Some(Ok(ChunkRecord::Chunk(chunk))) => {
+                    if let Some(v) = self.pending.replace((chunk, Vec::new())) {
+                        return Some(Ok(v));
+                    }
+                }
+                Some(Ok(ChunkRecord::IndexData(index))) => match &mut self.pending {
+                    Some((_, indexes)) => indexes.push(index),
+                    None => log::warn!("skipping index data which precedes all chunks"),
+                },
+                Some(Err(err)) => {
+                    return match self.pending.take() {
+                        Some(v) => {
+                            self.error = Some(err);
+                            Some(Ok(v))
+                        }
+                        None => Some(Err(err)),
+                    };
+                }
+                None => return self.pending.take().map(Ok),
+            }
+        }
+    }
+}
+
+impl<'a> FusedIterator for ChunksWithIndex<'a> {}
+
+/// Iterator over chunks at positions listed in `ChunkInfo` records.
+///
+/// Created by [`RosBag::indexed_chunks`][crate::RosBag::indexed_chunks].
+/// Iteration stops after the first error.
+pub struct IndexedChunksIterator<'a> {
+    pub(crate) cursor: Cursor<'a>,
+    pub(crate) offset: u64,
+    pub(crate) positions: std::vec::IntoIter<u64>,
+}
+
+impl<'a> Iterator for IndexedChunksIterator<'a> {
+    type Item = Result<Chunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.positions.next()?;
+        let res = pos
+            .checked_sub(self.offset)
+            .ok_or(Error::OutOfBounds)
+            .and_then(|pos| Ok(self.cursor.seek(pos)?))
+            .and_then(|()| match Record::next_record(&mut self.cursor)? {
+                Record::Chunk(chunk) => Ok(chunk),
+                v => Err(Error::UnexpectedChunkSectionRecord(v.kind())),
+            });
+        if res.is_err() {
+            self.positions = Vec::new().into_iter();
         }
-        let res = match Record::next_record(&mut self.cursor) {
-            Ok(Record::Chunk(v)) => Ok(ChunkRecord::Chunk(v)),
-            Ok(Record::IndexData(v)) => Ok(ChunkRecord::IndexData(v)),
-            Ok(v) => Err(Error::UnexpectedChunkSectionRecord(v.get_type())),
-            Err(e) => Err(e),
-        };
         Some(res)
     }
 }
+
+impl<'a> FusedIterator for IndexedChunksIterator<'a> {}
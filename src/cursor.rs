@@ -1,8 +1,19 @@
+use crate::record_types::chunk::{Decompressor, Decompressors};
+use crate::{Error, Result, UnknownFieldPolicy};
 use byteorder::{ByteOrder, LE};
 
+/// Beginning of the version line which starts every bag file.
+const VERSION_PREFIX: &[u8] = b"#ROSBAG V";
+
+#[derive(Clone)]
 pub(crate) struct Cursor<'a> {
     data: &'a [u8],
     pos: u64,
+    /// Position of `data` in the bag file, `None` for decompressed chunks
+    file_offset: Option<u64>,
+    limit: u32,
+    decompressors: Option<&'a Decompressors>,
+    unknown_fields: UnknownFieldPolicy,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -10,10 +21,82 @@ pub struct OutOfBounds;
 
 impl<'a> Cursor<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, pos: 0 }
+        Self {
+            data,
+            pos: 0,
+            file_offset: None,
+            limit: u32::MAX,
+            decompressors: None,
+            unknown_fields: UnknownFieldPolicy::Warn,
+        }
+    }
+
+    /// Create cursor over `data` with the same settings as `self`.
+    pub fn with_data(&self, data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            file_offset: None,
+            limit: self.limit,
+            decompressors: self.decompressors,
+            unknown_fields: self.unknown_fields,
+        }
+    }
+
+    /// Mark `data` as a section of the bag file starting at `offset`.
+    ///
+    /// Such cursors report version lines of concatenated bag files as
+    /// [`Error::ConcatenatedBag`] in `check_bag_start`.
+    pub fn with_file_offset(mut self, offset: u64) -> Self {
+        self.file_offset = Some(offset);
+        self
     }
 
-    pub fn seek(&mut self, pos: u64) -> Result<(), OutOfBounds> {
+    /// Check that another bag file does not start at the current position.
+    pub fn check_bag_start(&self) -> Result<()> {
+        let offset = match self.file_offset {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        if self.data[self.pos as usize..].starts_with(VERSION_PREFIX) {
+            return Err(Error::ConcatenatedBag {
+                pos: offset + self.pos,
+            });
+        }
+        Ok(())
+    }
+
+    /// Set maximum length of chunks read using `next_chunk`.
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Set registry used for decompression of custom compressed chunks.
+    pub fn with_decompressors(mut self, decompressors: &'a Decompressors) -> Self {
+        self.decompressors = Some(decompressors);
+        self
+    }
+
+    pub fn decompressor(&self, name: &str) -> Option<&'a Decompressor> {
+        self.decompressors?.get(name)
+    }
+
+    /// Set handling of unknown header fields in records read from the cursor.
+    pub fn with_unknown_field_policy(mut self, policy: UnknownFieldPolicy) -> Self {
+        self.unknown_fields = policy;
+        self
+    }
+
+    pub fn unknown_field_policy(&self) -> UnknownFieldPolicy {
+        self.unknown_fields
+    }
+
+    pub fn seek(&mut self, pos: u64) -> std::result::Result<(), OutOfBounds> {
         if pos > self.len() {
             return Err(OutOfBounds);
         }
@@ -21,6 +104,11 @@ impl<'a> Cursor<'a> {
         Ok(())
     }
 
+    /// Move to the end of data.
+    pub fn finish(&mut self) {
+        self.pos = self.len();
+    }
+
     pub fn pos(&self) -> u64 {
         self.pos
     }
@@ -33,7 +121,7 @@ impl<'a> Cursor<'a> {
         self.data.len() as u64 - self.pos()
     }
 
-    pub fn next_bytes(&mut self, n: u64) -> Result<&'a [u8], OutOfBounds> {
+    pub fn next_bytes(&mut self, n: u64) -> std::result::Result<&'a [u8], OutOfBounds> {
         if self.pos + n > self.len() {
             return Err(OutOfBounds);
         }
@@ -42,22 +130,34 @@ impl<'a> Cursor<'a> {
         Ok(&self.data[s..self.pos as usize])
     }
 
-    pub fn next_chunk(&mut self) -> Result<&'a [u8], OutOfBounds> {
-        let n = self.next_u32()? as u64;
-        self.next_bytes(n)
+    pub fn next_chunk(&mut self) -> Result<&'a [u8]> {
+        let n = self.next_u32()?;
+        if n > self.limit {
+            return Err(Error::RecordTooLarge {
+                declared: n,
+                limit: self.limit,
+            });
+        }
+        if n as u64 > self.left() {
+            return Err(Error::ImplausibleLength {
+                declared: n as u64,
+                available: self.left(),
+            });
+        }
+        Ok(self.next_bytes(n as u64)?)
     }
 
-    pub fn next_u32(&mut self) -> Result<u32, OutOfBounds> {
+    pub fn next_u32(&mut self) -> std::result::Result<u32, OutOfBounds> {
         Ok(LE::read_u32(self.next_bytes(4)?))
     }
 
     /*
-    pub fn next_u64(&mut self) -> Result<u64, OutOfBounds> {
+    pub fn next_u64(&mut self) -> std::result::Result<u64, OutOfBounds> {
         Ok(LE::read_u64(self.next_bytes(4)?))
     }
     */
 
-    pub fn next_time(&mut self) -> Result<u64, OutOfBounds> {
+    pub fn next_time(&mut self) -> std::result::Result<u64, OutOfBounds> {
         let s = self.next_u32()? as u64;
         let ns = self.next_u32()? as u64;
         Ok(1_000_000_000 * s + ns)
@@ -0,0 +1,228 @@
+use crate::record_types::Connection;
+use crate::{Error, IndexRecord, MessageRecord, Result, RosBag};
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::hash::Hasher;
+
+/// Message which is present in only one of the compared bags or which has
+/// different payloads in them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDiff {
+    /// Topic on which the message is stored
+    pub topic: String,
+    /// Time at which the message was received in nanoseconds of UNIX epoch
+    pub time: u64,
+}
+
+/// Differences between two bags found by [`RosBag::diff`].
+///
+/// "Missing" items are present only in the bag on which `diff` was called,
+/// "extra" items are present only in the other bag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BagDiff {
+    /// Topics without messages or connections in the other bag
+    pub missing_topics: Vec<String>,
+    /// Topics without messages or connections in this bag
+    pub extra_topics: Vec<String>,
+    /// Topics with different message types in the two bags
+    pub type_mismatches: Vec<String>,
+    /// Messages not present in the other bag
+    pub missing: Vec<MessageDiff>,
+    /// Messages not present in this bag
+    pub extra: Vec<MessageDiff>,
+    /// Messages with equal topic and time, but different payloads
+    pub differing: Vec<MessageDiff>,
+}
+
+impl BagDiff {
+    /// Check if no differences were found.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// `(time, payload hash)` pairs of messages of a topic in both compared bags.
+type TopicGroup = (Vec<(u64, u64)>, Vec<(u64, u64)>);
+
+/// Messages of a bag in time order as `(time, connection ID, payload hash)`.
+///
+/// Chunks are opened in the order of their start times, so only messages of
+/// chunks with overlapping time ranges are held in memory.
+struct TimeOrderedMessages<'a> {
+    bag: &'a RosBag,
+    /// `(start_time, chunk_pos)` of unopened chunks sorted in reverse order
+    chunks: Vec<(u64, u64)>,
+    /// Messages of opened chunks which were not taken yet
+    heap: BinaryHeap<Reverse<(u64, u32, u64)>>,
+}
+
+impl<'a> TimeOrderedMessages<'a> {
+    fn new(bag: &'a RosBag) -> Result<Self> {
+        bag.require_index()?;
+        let mut chunks = Vec::new();
+        for record in bag.index_records() {
+            if let IndexRecord::ChunkInfo(info) = record? {
+                chunks.push((info.start_time, info.chunk_pos));
+            }
+        }
+        chunks.sort_unstable_by(|a, b| b.cmp(a));
+        chunks.dedup();
+        Ok(Self {
+            bag,
+            chunks,
+            heap: BinaryHeap::new(),
+        })
+    }
+
+    /// Get time of the next message, opening chunks which may contain it.
+    fn peek_time(&mut self) -> Result<Option<u64>> {
+        while let Some(&(start, pos)) = self.chunks.last() {
+            if matches!(self.heap.peek(), Some(Reverse((t, ..))) if *t < start) {
+                break;
+            }
+            self.chunks.pop();
+            for msg in self.bag.chunk_record_at(pos)?.messages() {
+                if let MessageRecord::MessageData(msg) = msg? {
+                    let mut hasher = DefaultHasher::new();
+                    hasher.write(msg.data);
+                    self.heap
+                        .push(Reverse((msg.time, msg.conn_id, hasher.finish())));
+                }
+            }
+        }
+        Ok(self.heap.peek().map(|Reverse(m)| m.0))
+    }
+
+    /// Take `(connection ID, payload hash)` pairs of all messages with
+    /// timestamp `time`.
+    fn take_time(&mut self, time: u64) -> Result<Vec<(u32, u64)>> {
+        let mut res = Vec::new();
+        while self.peek_time()? == Some(time) {
+            let Reverse((_, conn_id, hash)) = self.heap.pop().expect("message was peeked");
+            res.push((conn_id, hash));
+        }
+        Ok(res)
+    }
+}
+
+impl RosBag {
+    /// Compare connections and messages with `other` bag.
+    ///
+    /// Messages are matched by topic, time and hash of their payload, so
+    /// messages of a topic can be spread across connections and chunks
+    /// differently in the two bags. Topics are compared as stored in
+    /// connection records (see [`Connection::storage_topic`]),
+    /// and the order of messages with equal timestamps does not matter.
+    ///
+    /// Both bags are streamed in time order using `ChunkInfo` records of
+    /// the index section, so only payload hashes of chunks with overlapping
+    /// time ranges are kept in memory. Messages in the result are sorted by
+    /// time. Returns [`Error::MissingIndex`] if any of the bags has no index.
+    pub fn diff(&self, other: &RosBag) -> Result<BagDiff> {
+        let mut buf_a = Vec::new();
+        let mut buf_b = Vec::new();
+        let conns_a = self.connection_map(&mut buf_a)?;
+        let conns_b = other.connection_map(&mut buf_b)?;
+        let topics = |conns: &HashMap<u32, Connection<'_>>| -> BTreeMap<String, String> {
+            conns
+                .values()
+                .map(|c| (c.storage_topic.to_string(), c.tp.to_string()))
+                .collect()
+        };
+        let (topics_a, topics_b) = (topics(&conns_a), topics(&conns_b));
+
+        let mut res = BagDiff::default();
+        for (topic, tp) in &topics_a {
+            match topics_b.get(topic) {
+                Some(other_tp) if other_tp != tp => res.type_mismatches.push(topic.clone()),
+                Some(_) => {}
+                None => res.missing_topics.push(topic.clone()),
+            }
+        }
+        res.extra_topics = topics_b
+            .keys()
+            .filter(|t| !topics_a.contains_key(*t))
+            .cloned()
+            .collect();
+
+        let mut a = TimeOrderedMessages::new(self)?;
+        let mut b = TimeOrderedMessages::new(other)?;
+        loop {
+            let time = match (a.peek_time()?, b.peek_time()?) {
+                (Some(x), Some(y)) => x.min(y),
+                (Some(x), None) | (None, Some(x)) => x,
+                (None, None) => break,
+            };
+            let mut groups: BTreeMap<&str, TopicGroup> = BTreeMap::new();
+            for (conn_id, hash) in a.take_time(time)? {
+                let topic = conns_a
+                    .get(&conn_id)
+                    .ok_or(Error::InvalidRecord)?
+                    .storage_topic;
+                if topics_b.contains_key(topic) {
+                    groups.entry(topic).or_default().0.push((time, hash));
+                }
+            }
+            for (conn_id, hash) in b.take_time(time)? {
+                let topic = conns_b
+                    .get(&conn_id)
+                    .ok_or(Error::InvalidRecord)?
+                    .storage_topic;
+                if topics_a.contains_key(topic) {
+                    groups.entry(topic).or_default().1.push((time, hash));
+                }
+            }
+            for (topic, (mut msgs_a, mut msgs_b)) in groups {
+                msgs_a.sort_unstable();
+                msgs_b.sort_unstable();
+                diff_messages(topic, &msgs_a, &msgs_b, &mut res);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Compare two lists of messages sorted by time and payload hash.
+///
+/// Messages with equal time and hash are matched, remaining messages with
+/// equal time are reported as differing in pairs.
+fn diff_messages(topic: &str, mut a: &[(u64, u64)], mut b: &[(u64, u64)], res: &mut BagDiff) {
+    let entry = |time| MessageDiff {
+        topic: topic.to_string(),
+        time,
+    };
+    while let Some(time) = a.first().into_iter().chain(b.first()).map(|m| m.0).min() {
+        let (group_a, rest_a) = a.split_at(a.partition_point(|m| m.0 == time));
+        let (group_b, rest_b) = b.split_at(b.partition_point(|m| m.0 == time));
+        a = rest_a;
+        b = rest_b;
+
+        let (mut i, mut j) = (0, 0);
+        let (mut unmatched_a, mut unmatched_b) = (0, 0);
+        while i < group_a.len() && j < group_b.len() {
+            match group_a[i].1.cmp(&group_b[j].1) {
+                Ordering::Equal => {
+                    i += 1;
+                    j += 1;
+                }
+                Ordering::Less => {
+                    unmatched_a += 1;
+                    i += 1;
+                }
+                Ordering::Greater => {
+                    unmatched_b += 1;
+                    j += 1;
+                }
+            }
+        }
+        unmatched_a += group_a.len() - i;
+        unmatched_b += group_b.len() - j;
+        let differing = unmatched_a.min(unmatched_b);
+        res.differing.extend((0..differing).map(|_| entry(time)));
+        res.missing
+            .extend((differing..unmatched_a).map(|_| entry(time)));
+        res.extra
+            .extend((differing..unmatched_b).map(|_| entry(time)));
+    }
+}
@@ -0,0 +1,28 @@
+//! High-level helpers for the common case of reading a whole bag file.
+use crate::{LoadedBag, RosBag};
+use std::{io, path::Path};
+
+/// Open ROS bag file at `path` and load all its chunks and connections into
+/// memory.
+///
+/// Parsing errors are returned as [`io::ErrorKind::InvalidData`] with the
+/// underlying [`Error`][crate::Error] as the inner error. See
+/// [`RosBag::load_all`] for memory usage considerations.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = "dummy.bag";
+/// let bag = rosbag::easy::open(path)?;
+/// for msg in bag.messages() {
+///     let msg = msg?;
+///     // ..
+///     # drop(msg);
+/// }
+/// # Ok(()) }
+/// ```
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LoadedBag> {
+    RosBag::new(path)?
+        .load_all()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -1,4 +1,5 @@
 use crate::cursor::OutOfBounds;
+use crate::record_types::Compression;
 use std::convert::From;
 use std::fmt;
 
@@ -9,20 +10,106 @@ pub enum Error {
     InvalidHeader,
     /// Invalid record.
     InvalidRecord,
+    /// Record with unknown `op` header field value.
+    UnknownRecord(u8),
     /// Encountered unsupported version in record.
     UnsupportedVersion,
+    /// Bag file uses unsupported format version (e.g. `"1.2"`).
+    UnsupportedBagVersion(String),
     /// Tried to access outside of rosbag file.
     OutOfBounds,
+    /// Record declares length bigger than the configured limit.
+    ///
+    /// See [`OpenOptions::max_record_size`][crate::OpenOptions::max_record_size].
+    RecordTooLarge {
+        /// Length declared in the record
+        declared: u32,
+        /// Configured limit
+        limit: u32,
+    },
+    /// Length or position stored in the file points past the available data.
+    ///
+    /// Usually caused by a truncated or corrupted file, or by a file which
+    /// is not a little-endian ROS bag.
+    ImplausibleLength {
+        /// Length or position read from the file
+        declared: u64,
+        /// Number of bytes available
+        available: u64,
+    },
+    /// Index position stored in the bag header points before the end of the
+    /// header or past the end of file.
+    InvalidIndexPosition {
+        /// Index position stored in the bag header
+        index_pos: u64,
+        /// Position of the first byte after the bag header record
+        header_end: u64,
+        /// Length of the file
+        file_len: u64,
+    },
+    /// Another bag file starts after the index section, e.g. because several
+    /// bag files were concatenated into one.
+    ///
+    /// Concatenated bags are not supported, but records of the first bag
+    /// are still accessible.
+    ConcatenatedBag {
+        /// Position of the version line of the next bag file
+        pos: u64,
+    },
+    /// Operation requires the index section, which is missing or corrupted.
+    ///
+    /// Returned only for bags opened with
+    /// [`OpenOptions::tolerate_missing_index`][crate::OpenOptions::tolerate_missing_index].
+    MissingIndex,
+    /// Number of connections in the index section does not match the bag
+    /// header.
+    ConnectionCountMismatch {
+        /// Connection count stored in the bag header
+        expected: u32,
+        /// Number of connections found in the index section
+        actual: usize,
+    },
+    /// Connection record contains invalid `md5sum` field.
+    InvalidMd5 {
+        /// Topic of the connection, if known
+        topic: Option<String>,
+        /// Description of the problem
+        reason: &'static str,
+    },
+    /// Time bucket size is zero or results in too many buckets for the time
+    /// span of the data.
+    InvalidBucketSize {
+        /// Requested bucket size in nanoseconds
+        bucket_ns: u64,
+    },
     /// Got unexpected record type in the chunk section.
     UnexpectedChunkSectionRecord(&'static str),
     /// Got unexpected record type in the index section.
     UnexpectedIndexSectionRecord(&'static str),
     /// Got unexpected record type inside [`Chunk`][crate::record_types::Chunk] payload.
     UnexpectedMessageRecord(&'static str),
+    /// Chunk uses compression without a registered decompressor.
+    UnsupportedCompression(String),
+    /// Header contains a field which is not known to the crate, reported
+    /// only with [`UnknownFieldPolicy::Error`][crate::UnknownFieldPolicy::Error].
+    UnknownField(String),
     /// Bzip2 decompression failure.
     Bzip2DecompressionError(String),
     /// Lz4 decompression failure.
     Lz4DecompressionError(String),
+    /// Size of decompressed chunk data does not match size declared in the
+    /// chunk header.
+    ///
+    /// Usually caused by a truncated file or by a compressed stream which is
+    /// not fully supported by the decompressor (e.g. multi-stream bzip2 data).
+    DecompressionSizeMismatch {
+        /// Size declared in the chunk header
+        expected: u32,
+        /// Size of decompressed data
+        actual: usize,
+        /// Compression used by the chunk
+        compression: Compression,
+    },
 }
 
 impl From<OutOfBounds> for Error {
@@ -37,13 +124,64 @@ impl fmt::Display for Error {
         let s = match self {
             InvalidHeader => "invalid header".to_string(),
             InvalidRecord => "invalid record".to_string(),
+            UnknownRecord(op) => format!("unknown record op: {:#04x}", op),
             UnsupportedVersion => "unsupported version".to_string(),
+            UnsupportedBagVersion(v) => format!("unsupported bag format version {}", v),
             OutOfBounds => "out of bounds".to_string(),
+            RecordTooLarge { declared, limit } => format!(
+                "record length {} is bigger than the limit {}",
+                declared, limit
+            ),
+            ImplausibleLength {
+                declared,
+                available,
+            } => format!(
+                "declared length {} exceeds {} available bytes \
+                 (corrupted file or format/endianness mismatch?)",
+                declared, available
+            ),
+            InvalidIndexPosition {
+                index_pos,
+                header_end,
+                file_len,
+            } => format!(
+                "index position {} is outside of the [{}, {}] range \
+                 between the bag header end and the file end",
+                index_pos, header_end, file_len
+            ),
+            ConcatenatedBag { pos } => format!(
+                "another bag file starts at position {} (concatenated bags are not supported)",
+                pos
+            ),
+            MissingIndex => "bag file has no usable index section".to_string(),
+            ConnectionCountMismatch { expected, actual } => format!(
+                "found {} connections in the index section, expected {}",
+                actual, expected
+            ),
+            InvalidMd5 {
+                topic: Some(topic),
+                reason,
+            } => format!("invalid md5sum of connection on {}: {}", topic, reason),
+            InvalidMd5 {
+                topic: None,
+                reason,
+            } => format!("invalid md5sum of connection: {}", reason),
+            InvalidBucketSize { bucket_ns } => format!("invalid time bucket size {} ns", bucket_ns),
             UnexpectedChunkSectionRecord(t) => format!("unexpected {} in the chunk section", t),
             UnexpectedIndexSectionRecord(t) => format!("unexpected {} in the index section", t),
             UnexpectedMessageRecord(t) => format!("unexpected {} in chunk payload", t),
+            UnsupportedCompression(name) => format!("unsupported chunk compression {:?}", name),
+            UnknownField(name) => format!("unknown header field {:?}", name),
             Bzip2DecompressionError(e) => format!("bzip2 decompression error: {}", e),
             Lz4DecompressionError(e) => format!("LZ4 decompression error: {}", e),
+            DecompressionSizeMismatch {
+                expected,
+                actual,
+                compression,
+            } => format!(
+                "{:?} decompressed chunk has size {}, expected {}",
+                compression, actual, expected
+            ),
         };
         write!(f, "rosbag::Error: {}", s)
     }
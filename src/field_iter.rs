@@ -3,13 +3,20 @@ use crate::record_types::utils::read_record;
 use std::iter::Iterator;
 use std::str;
 
-/// Iterator which goes over record header fields
-pub(crate) struct FieldIterator<'a> {
+/// Iterator which goes over record header fields.
+///
+/// Yields `(name, value)` pairs of the `<len><name>=<value>` fields stored
+/// in a record header. Useful for inspecting fields which are not modeled
+/// by the record types of this crate.
+#[derive(Debug, Clone)]
+pub struct FieldIterator<'a> {
     buf: &'a [u8],
 }
 
 impl<'a> FieldIterator<'a> {
-    pub(crate) fn new(buf: &'a [u8]) -> Self {
+    /// Create iterator over fields in the header buffer `buf` (without
+    /// the header length prefix).
+    pub fn new(buf: &'a [u8]) -> Self {
         Self { buf }
     }
 }
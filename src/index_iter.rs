@@ -1,5 +1,6 @@
-use crate::record_types::{ChunkInfo, Connection, IndexData};
-use crate::{record::Record, Cursor, Error, Result};
+use crate::record::{check_record_at, skip_unknown_record, Record};
+use crate::record_types::{ChunkInfo, Connection, IndexData, RecordGen};
+use crate::{Cursor, Error, Result};
 
 /// Record types which can be stored in the chunk section.
 #[derive(Debug, Clone)]
@@ -13,9 +14,12 @@ pub enum IndexRecord<'a> {
 }
 
 /// Iterator over records stored in the chunk section of a rosbag file.
+///
+/// Iteration stops after the first error, but it can be resumed with `seek`.
 pub struct IndexRecordsIterator<'a> {
     pub(crate) cursor: Cursor<'a>,
     pub(crate) offset: u64,
+    pub(crate) skip_unknown: bool,
 }
 
 impl<'a> IndexRecordsIterator<'a> {
@@ -31,22 +35,64 @@ impl<'a> IndexRecordsIterator<'a> {
         }
         Ok(self.cursor.seek(pos - self.offset)?)
     }
+
+    /// Jump to the given position in the file after checking that it points
+    /// to a record which can be stored in the index section.
+    ///
+    /// Lengths of the record header and data are checked against the file
+    /// size and [`OpenOptions::max_record_size`][crate::OpenOptions::max_record_size].
+    /// On error the iterator position is left unchanged.
+    pub fn seek_validated(&mut self, pos: u64) -> Result<()> {
+        if pos < self.offset {
+            return Err(Error::OutOfBounds);
+        }
+        let pos = pos - self.offset;
+        let ops = [IndexData::OP, Connection::OP, ChunkInfo::OP];
+        check_record_at(&self.cursor, pos, &ops, Error::UnexpectedIndexSectionRecord)?;
+        Ok(self.cursor.seek(pos)?)
+    }
+
+    /// Skip records with unknown `op` values instead of returning an error.
+    ///
+    /// Skipped records are reported using `log::warn!`. Useful for reading
+    /// files which contain vendor-specific record types. Legacy message
+    /// definition records (`op=0x01`) are always skipped. Default: `false`.
+    pub fn skip_unknown(mut self, skip: bool) -> Self {
+        self.skip_unknown = skip;
+        self
+    }
+
+    /// Get current position in the file.
+    ///
+    /// Returned value can be later passed to [`seek`][Self::seek] to resume
+    /// iteration from the same record.
+    pub fn position(&self) -> u64 {
+        self.offset + self.cursor.pos()
+    }
 }
 
 impl<'a> Iterator for IndexRecordsIterator<'a> {
     type Item = Result<IndexRecord<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cursor.left() == 0 {
-            return None;
+        loop {
+            if self.cursor.left() == 0 {
+                return None;
+            }
+            let res = match Record::next_record(&mut self.cursor) {
+                Err(Error::UnknownRecord(op)) if skip_unknown_record(op, self.skip_unknown) => {
+                    continue
+                }
+                Ok(Record::IndexData(v)) => Ok(IndexRecord::IndexData(v)),
+                Ok(Record::Connection(v)) => Ok(IndexRecord::Connection(v)),
+                Ok(Record::ChunkInfo(v)) => Ok(IndexRecord::ChunkInfo(v)),
+                Ok(v) => Err(Error::UnexpectedIndexSectionRecord(v.kind())),
+                Err(e) => Err(e),
+            };
+            if res.is_err() {
+                self.cursor.finish();
+            }
+            return Some(res);
         }
-        let res = match Record::next_record(&mut self.cursor) {
-            Ok(Record::IndexData(v)) => Ok(IndexRecord::IndexData(v)),
-            Ok(Record::Connection(v)) => Ok(IndexRecord::Connection(v)),
-            Ok(Record::ChunkInfo(v)) => Ok(IndexRecord::ChunkInfo(v)),
-            Ok(v) => Err(Error::UnexpectedIndexSectionRecord(v.get_type())),
-            Err(e) => Err(e),
-        };
-        Some(res)
     }
 }
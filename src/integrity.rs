@@ -0,0 +1,231 @@
+use crate::record::{next_record_bytes, read_op, Record};
+use crate::record_types::chunk::ChunkHeader;
+use crate::record_types::{Chunk, Compression, HeaderGen, RecordGen};
+use crate::{Cursor, Error, MessageRecord, RosBag};
+use std::iter::FusedIterator;
+
+/// Event reported by [`RosBag::scan_integrity`].
+#[derive(Debug)]
+pub enum IntegrityEvent {
+    /// Chunk was decompressed and all records in it were parsed successfully.
+    ChunkOk {
+        /// Position of the chunk record in the file
+        pos: u64,
+    },
+    /// Chunk could not be decompressed or contains invalid records.
+    ChunkError {
+        /// Position of the chunk record in the file
+        pos: u64,
+        /// First error encountered in the chunk
+        error: Error,
+    },
+    /// Record other than chunk could not be parsed.
+    RecordError {
+        /// Position of the record in the file
+        pos: u64,
+        /// Parsing error
+        error: Error,
+    },
+    /// Final event with totals of the scan.
+    Summary {
+        /// Number of chunks without errors
+        chunks_ok: u32,
+        /// Number of chunks with errors
+        chunks_failed: u32,
+        /// Number of other records with errors
+        records_failed: u32,
+    },
+}
+
+/// Results of [`RosBag::recoverable_scan`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Number of recovered `MessageData` records
+    pub messages: u64,
+    /// Number of chunks which were read only partially or not at all
+    pub damaged_chunks: u32,
+    /// Number of bytes which could not be read, counted in decompressed
+    /// chunk data for chunks which were decompressed successfully
+    pub unreadable_bytes: u64,
+}
+
+/// Iterator which checks integrity of all records in the bag file.
+///
+/// Created by [`RosBag::scan_integrity`].
+pub struct IntegrityScan<'a> {
+    cursor: Cursor<'a>,
+    offset: u64,
+    chunks_ok: u32,
+    chunks_failed: u32,
+    records_failed: u32,
+    done: bool,
+}
+
+impl<'a> IntegrityScan<'a> {
+    fn check_record(&self, buf: &[u8]) -> Result<bool, Error> {
+        let mut c = self.cursor.with_data(buf);
+        match Record::next_record(&mut c)? {
+            Record::Chunk(chunk) => {
+                for msg in chunk.messages() {
+                    msg?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<'a> Iterator for IntegrityScan<'a> {
+    type Item = IntegrityEvent;
+
+    fn next(&mut self) -> Option<IntegrityEvent> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if self.cursor.left() == 0 {
+                self.done = true;
+                return Some(IntegrityEvent::Summary {
+                    chunks_ok: self.chunks_ok,
+                    chunks_failed: self.chunks_failed,
+                    records_failed: self.records_failed,
+                });
+            }
+            let pos = self.offset + self.cursor.pos();
+            let (op, buf) = match next_record_bytes(&mut self.cursor) {
+                Ok(v) => v,
+                Err(error) => {
+                    // record boundaries are lost, so the rest can't be checked
+                    self.cursor.finish();
+                    self.records_failed += 1;
+                    return Some(IntegrityEvent::RecordError { pos, error });
+                }
+            };
+            let is_chunk = op == Chunk::OP;
+            return Some(match self.check_record(buf) {
+                Ok(true) => {
+                    self.chunks_ok += 1;
+                    IntegrityEvent::ChunkOk { pos }
+                }
+                Ok(false) => continue,
+                Err(error) if is_chunk => {
+                    self.chunks_failed += 1;
+                    IntegrityEvent::ChunkError { pos, error }
+                }
+                Err(error) => {
+                    self.records_failed += 1;
+                    IntegrityEvent::RecordError { pos, error }
+                }
+            });
+        }
+    }
+}
+
+impl<'a> FusedIterator for IntegrityScan<'a> {}
+
+impl RosBag {
+    /// Check integrity of all records stored after the bag header.
+    ///
+    /// Unlike regular iteration, the scan does not stop at the first error:
+    /// every chunk gets decompressed and all its records get parsed, and
+    /// failures are reported as events with positions of the affected
+    /// records. The last event is always [`IntegrityEvent::Summary`].
+    /// Scanning stops early only if record boundaries can not be determined
+    /// anymore (e.g. because of a truncated file).
+    pub fn scan_integrity(&self) -> IntegrityScan<'_> {
+        IntegrityScan {
+            cursor: self.cursor(self.start_pos, self.data.len()),
+            offset: self.start_pos as u64,
+            chunks_ok: 0,
+            chunks_failed: 0,
+            records_failed: 0,
+            done: false,
+        }
+    }
+
+    /// Read as many records as possible from all chunks.
+    ///
+    /// `f` gets called for every `MessageData` and `Connection` record which
+    /// can be parsed. Unlike regular iteration, reading of a chunk stops
+    /// at the first invalid record inside it and the scan continues with the
+    /// next chunk. Records of a truncated uncompressed chunk at the end of
+    /// the chunk section are recovered up to the truncation point. Use
+    /// [`OpenOptions::tolerate_missing_index`][crate::OpenOptions::tolerate_missing_index]
+    /// to open bags written by killed recorders.
+    pub fn recoverable_scan<F>(&self, mut f: F) -> RecoveryReport
+    where
+        F: FnMut(MessageRecord<'_>),
+    {
+        let mut report = RecoveryReport::default();
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        while cursor.left() != 0 {
+            let pos = cursor.pos();
+            let (op, buf) = match next_record_bytes(&mut cursor) {
+                Ok(v) => v,
+                Err(_) => {
+                    // record boundaries are lost, try to salvage the rest
+                    cursor.seek(pos).expect("position was valid");
+                    report.damaged_chunks += 1;
+                    report.unreadable_bytes += match truncated_chunk_data(&mut cursor) {
+                        Some(data) => scan_chunk_data(&cursor, data, &mut report, &mut f),
+                        None => cursor.left(),
+                    };
+                    break;
+                }
+            };
+            if op != Chunk::OP {
+                continue;
+            }
+            let unreadable = match Record::next_record(&mut cursor.with_data(buf)) {
+                Ok(Record::Chunk(chunk)) => {
+                    scan_chunk_data(&cursor, chunk.decompressed_bytes(), &mut report, &mut f)
+                }
+                _ => buf.len() as u64,
+            };
+            if unreadable != 0 {
+                report.damaged_chunks += 1;
+                report.unreadable_bytes += unreadable;
+            }
+        }
+        report
+    }
+}
+
+/// Get data of an uncompressed chunk truncated by the end of `c`.
+fn truncated_chunk_data<'a>(c: &mut Cursor<'a>) -> Option<&'a [u8]> {
+    let header = c.next_chunk().ok()?;
+    if read_op(header).ok()? != Chunk::OP {
+        return None;
+    }
+    match ChunkHeader::read_header(header, c.unknown_field_policy())
+        .ok()?
+        .compression?
+    {
+        Compression::None => {}
+        _ => return None,
+    }
+    c.next_u32().ok()?;
+    c.next_bytes(c.left()).ok()
+}
+
+/// Pass valid records in chunk `data` to `f` and return number of bytes
+/// which could not be read.
+fn scan_chunk_data<F>(c: &Cursor<'_>, data: &[u8], report: &mut RecoveryReport, f: &mut F) -> u64
+where
+    F: FnMut(MessageRecord<'_>),
+{
+    let mut c = c.with_data(data);
+    while c.left() != 0 {
+        let left = c.left();
+        match Record::next_record(&mut c) {
+            Ok(Record::MessageData(msg)) => {
+                report.messages += 1;
+                f(MessageRecord::MessageData(msg));
+            }
+            Ok(Record::Connection(conn)) => f(MessageRecord::Connection(conn)),
+            _ => return left,
+        }
+    }
+    0
+}
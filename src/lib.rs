@@ -52,60 +52,120 @@
 //! ```
 #![warn(missing_docs, rust_2018_idioms)]
 
-use memmap2::Mmap;
-use std::{fs, io, path::Path, result, str};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::{io, path::Path, result, str};
 
 const VERSION_STRING: &str = "#ROSBAG V2.0\n";
 const VERSION_LEN: u64 = VERSION_STRING.len() as u64;
 const ROSBAG_HEADER_OP: u8 = 0x03;
 
+#[macro_use]
+mod trace;
+
 mod cursor;
+mod diff;
 mod error;
 mod field_iter;
+mod integrity;
+mod loaded;
+mod locator;
+mod options;
+mod parallel;
+mod raw_iter;
 mod record;
+mod stats;
+mod storage;
+mod time_index;
+mod time_windows;
 
 mod chunk_iter;
 mod index_iter;
 mod msg_iter;
 pub mod record_types;
 
+pub mod easy;
+pub mod prelude;
+
 use cursor::Cursor;
-use field_iter::FieldIterator;
-use record_types::utils::{check_op, set_field_u32, set_field_u64};
+use record_types::chunk::Decompressors;
+use record_types::utils::{check_op, set_field_u32, set_field_u64, unknown_field};
+use record_types::{ChunkInfo, Connection, MessageData};
+use storage::Storage;
 
-pub use chunk_iter::{ChunkRecord, ChunkRecordsIterator};
+pub use chunk_iter::{ChunkRecord, ChunkRecordsIterator, ChunksWithIndex, IndexedChunksIterator};
+pub use diff::{BagDiff, MessageDiff};
 pub use error::Error;
+pub use field_iter::FieldIterator;
 pub use index_iter::{IndexRecord, IndexRecordsIterator};
-pub use msg_iter::{MessageRecord, MessageRecordsIterator};
+pub use integrity::{IntegrityEvent, IntegrityScan, RecoveryReport};
+pub use loaded::LoadedBag;
+pub use locator::MessageLocator;
+pub use msg_iter::{Decimator, MessageRecord, MessageRecordsIterator, ResolvedMessages};
+pub use options::{OpenOptions, UnknownFieldPolicy};
+pub use parallel::ParallelMessages;
+pub use raw_iter::{RawChunk, RawChunksIterator, RawRecordsIterator};
+pub use record::Record;
+pub use stats::{CompressionStats, ConnectionUsage, ScanStats};
+pub use time_index::{BagTimeIndex, TimeIndexEntry};
 
 /// Open rosbag file.
+///
+/// Cloning is cheap, since clones share the same memory mapping of the file
+/// (or the same buffer for bags read using [`RosBag::from_reader`]).
+#[derive(Clone)]
 pub struct RosBag {
-    data: Mmap,
+    data: Arc<Storage>,
     start_pos: usize,
+    /// End of the chunk section, equal to `header.index_pos` unless it is
+    /// invalid and the index is tolerated to be missing
     index_pos: usize,
-    conn_count: u32,
-    chunk_count: u32,
+    header: BagHeaderInfo,
+    max_record_size: u32,
+    decompressors: Arc<Decompressors>,
+    has_index: bool,
+    unknown_fields: UnknownFieldPolicy,
 }
 
 /// A specialized Result type for ROS bag file reading and parsing.
 pub type Result<T> = result::Result<T, Error>;
 
 /// Bag file header record which contains basic information about the file.
-#[derive(Debug, Clone)]
-struct BagHeader {
+///
+/// Returned by [`RosBag::header`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BagHeaderInfo {
     /// Offset of first record after the chunk section
-    index_pos: u64,
+    pub index_pos: u64,
     /// Number of unique connections in the file
-    conn_count: u32,
+    pub conn_count: u32,
     /// Number of chunk records in the file
-    chunk_count: u32,
+    pub chunk_count: u32,
+}
+
+/// Extract version from a `#ROSBAG V<major>.<minor>\n` line.
+fn parse_version(line: &[u8]) -> Option<&str> {
+    let v = line.strip_prefix(b"#ROSBAG V")?.strip_suffix(b"\n")?;
+    let v = str::from_utf8(v).ok()?;
+    let (major, minor) = v.split_once('.')?;
+    let is_num = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if is_num(major) && is_num(minor) {
+        Some(v)
+    } else {
+        None
+    }
 }
 
-fn parse_bag_header(data: &[u8]) -> Result<(u64, BagHeader)> {
+fn parse_bag_header(data: &[u8], policy: UnknownFieldPolicy) -> Result<(u64, BagHeaderInfo)> {
+    let _span = span!(DEBUG, "parse_bag_header", file_len = data.len());
     let mut cursor = Cursor::new(data);
 
-    if cursor.next_bytes(VERSION_LEN)? != VERSION_STRING.as_bytes() {
-        return Err(Error::InvalidHeader);
+    let version = cursor.next_bytes(VERSION_LEN)?;
+    if version != VERSION_STRING.as_bytes() {
+        return Err(match parse_version(version) {
+            Some(v) => Error::UnsupportedBagVersion(v.to_string()),
+            None => Error::InvalidHeader,
+        });
     }
 
     let header = cursor.next_chunk()?;
@@ -125,12 +185,12 @@ fn parse_bag_header(data: &[u8]) -> Result<(u64, BagHeader)> {
             "index_pos" => set_field_u64(&mut index_pos, val)?,
             "conn_count" => set_field_u32(&mut conn_count, val)?,
             "chunk_count" => set_field_u32(&mut chunk_count, val)?,
-            _ => log::warn!("unexpected field in bag header: {}", name),
+            _ => unknown_field(name, val, policy)?,
         }
     }
 
     let bag_header = match (index_pos, conn_count, chunk_count, op) {
-        (Some(index_pos), Some(conn_count), Some(chunk_count), true) => BagHeader {
+        (Some(index_pos), Some(conn_count), Some(chunk_count), true) => BagHeaderInfo {
             index_pos,
             conn_count,
             chunk_count,
@@ -146,50 +206,582 @@ fn parse_bag_header(data: &[u8]) -> Result<(u64, BagHeader)> {
 
 impl RosBag {
     /// Create a new iterator over provided path to ROS bag file.
+    ///
+    /// Only version 2.0 of the format is supported. Parsing errors are
+    /// returned as [`io::ErrorKind::InvalidData`] with the underlying
+    /// [`Error`] as the inner error, e.g. [`Error::UnsupportedBagVersion`]
+    /// for files written in older formats.
+    ///
+    /// Use [`OpenOptions`] to configure how the file gets opened.
     pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let data = unsafe { Mmap::map(&fs::File::open(path)?)? };
-
-        let (start_pos, header) = parse_bag_header(&data).map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid or unsupported rosbag header",
-            )
-        })?;
+        OpenOptions::new().open(path)
+    }
 
-        Ok(Self {
-            data,
-            start_pos: start_pos.try_into().unwrap(),
-            conn_count: header.conn_count,
-            index_pos: header.index_pos.try_into().unwrap(),
-            chunk_count: header.chunk_count,
-        })
+    /// Read ROS bag file of `size` bytes from `reader` into memory.
+    ///
+    /// See [`OpenOptions::open_reader`] for details.
+    pub fn from_reader<R: io::Read>(reader: R, size: u64) -> io::Result<Self> {
+        OpenOptions::new().open_reader(reader, size)
     }
 
     /// Get connection count in this rosbag file.
     pub fn get_conn_count(&self) -> u32 {
-        self.conn_count
+        self.header.conn_count
     }
 
     /// Get chunk count in this rosbag file.
     pub fn get_chunk_count(&self) -> u32 {
-        self.chunk_count
+        self.header.chunk_count
+    }
+
+    /// Check if the index section is available.
+    ///
+    /// Always `true` unless the bag was opened with
+    /// [`OpenOptions::tolerate_missing_index`] and the index section was
+    /// found to be missing or corrupted.
+    pub fn has_index(&self) -> bool {
+        self.has_index
+    }
+
+    /// Get fields of the bag header record.
+    ///
+    /// Values are returned as stored in the file. The chunk section spans
+    /// from the end of the bag header record up to `index_pos`, or up to the
+    /// end of file if `index_pos` is invalid and the bag was opened with
+    /// [`OpenOptions::tolerate_missing_index`], see
+    /// [`RosBag::chunk_section_range`].
+    pub fn header(&self) -> BagHeaderInfo {
+        self.header
+    }
+
+    /// Get range of file positions occupied by the chunk section.
+    pub fn chunk_section_range(&self) -> std::ops::Range<u64> {
+        self.start_pos as u64..self.index_pos as u64
     }
 
     /// Get iterator over records in the chunk section.
+    ///
+    /// Chunks are decompressed one at a time as the iterator advances, and
+    /// the decompressed data is freed once the yielded [`Chunk`][record_types::Chunk] is dropped.
+    /// Thus memory usage is bounded by the size of the largest chunk, which
+    /// can be limited with [`OpenOptions::max_record_size`].
     pub fn chunk_records(&self) -> ChunkRecordsIterator<'_> {
-        let cursor = Cursor::new(&self.data[self.start_pos..self.index_pos]);
+        let cursor = self.cursor(self.start_pos, self.index_pos);
         ChunkRecordsIterator {
             cursor,
             offset: self.start_pos as u64,
+            end: self.index_pos as u64,
+            skip_unknown: false,
         }
     }
 
+    /// Get iterator over records in the chunk section which start in the
+    /// `[start, end)` range of file positions.
+    ///
+    /// A record belongs to the range in which it starts, even if it ends
+    /// after `end`. If `start` does not point to a record beginning,
+    /// iteration starts from the first record which begins after it. Thus
+    /// splitting the chunk section into consecutive ranges at arbitrary
+    /// positions results in every record being processed by exactly one
+    /// iterator. Record boundaries are found by following length prefixes
+    /// of records from the start of the chunk section, without reading
+    /// record data.
+    pub fn chunk_records_range(&self, start: u64, end: u64) -> Result<ChunkRecordsIterator<'_>> {
+        if start > end || end > self.index_pos as u64 {
+            return Err(Error::OutOfBounds);
+        }
+        let mut iter = self.chunk_records();
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        while iter.offset + cursor.pos() < start && cursor.left() != 0 {
+            cursor.next_chunk()?;
+            cursor.next_chunk()?;
+        }
+        iter.seek(iter.offset + cursor.pos())?;
+        iter.end = end;
+        Ok(iter)
+    }
+
+    /// Get iterator over chunks at positions listed in `ChunkInfo` records.
+    ///
+    /// Unlike [`RosBag::chunk_records`], this does not depend on the layout
+    /// of the chunk section: chunks are visited in the order of their
+    /// positions, each chunk exactly once even if it is listed by several
+    /// `ChunkInfo` records, and records between chunks are not read.
+    pub fn indexed_chunks(&self) -> Result<IndexedChunksIterator<'_>> {
+        self.require_index()?;
+        let mut positions = Vec::with_capacity(self.header.chunk_count as usize);
+        for record in self.index_records() {
+            if let IndexRecord::ChunkInfo(info) = record? {
+                positions.push(info.chunk_pos);
+            }
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        Ok(IndexedChunksIterator {
+            cursor: self.cursor(self.start_pos, self.index_pos),
+            offset: self.start_pos as u64,
+            positions: positions.into_iter(),
+        })
+    }
+
     /// Get iterator over records in the index section.
     pub fn index_records(&self) -> IndexRecordsIterator<'_> {
-        let cursor = Cursor::new(&self.data[self.index_pos..]);
+        let cursor = self.cursor(self.index_pos, self.index_end());
         IndexRecordsIterator {
             cursor,
             offset: self.index_pos as u64,
+            skip_unknown: false,
+        }
+    }
+
+    /// Get iterator over raw bytes of all records stored after the bag
+    /// header, in both the chunk and the index sections.
+    pub fn raw_records(&self) -> RawRecordsIterator<'_> {
+        let cursor = self.cursor(self.start_pos, self.data.len());
+        RawRecordsIterator {
+            cursor,
+            offset: self.start_pos as u64,
+        }
+    }
+
+    /// Read header fields of the record which starts at file position `pos`.
+    ///
+    /// Record data is not read, so the position can be checked before
+    /// seeking an iterator to it. Fields are returned in the order in which
+    /// they are stored in the header.
+    pub fn read_record_header(&self, pos: u64) -> Result<Vec<(&str, &[u8])>> {
+        let pos = pos
+            .checked_sub(self.start_pos as u64)
+            .ok_or(Error::OutOfBounds)?;
+        let mut cursor = self.cursor(self.start_pos, self.data.len());
+        cursor.seek(pos)?;
+        FieldIterator::new(cursor.next_chunk()?).collect()
+    }
+
+    /// Get iterator over chunks in the chunk section with their data left
+    /// compressed.
+    ///
+    /// Useful for forwarding chunks verbatim, e.g. when copying bags to
+    /// another storage, as no time is spent on decompression.
+    pub fn raw_chunks(&self) -> RawChunksIterator<'_> {
+        RawChunksIterator {
+            cursor: self.cursor(self.start_pos, self.index_pos),
+            offset: self.start_pos as u64,
+        }
+    }
+
+    /// Get all connections stored in the index section.
+    ///
+    /// Returns [`Error::ConnectionCountMismatch`] if number of found
+    /// connections does not match connection count in the bag header.
+    pub fn index_connections(&self) -> Result<Vec<Connection<'_>>> {
+        self.require_index()?;
+        let mut res = Vec::with_capacity(self.header.conn_count as usize);
+        for record in self.index_records() {
+            if let IndexRecord::Connection(conn) = record? {
+                res.push(conn);
+            }
+        }
+        if res.len() != self.header.conn_count as usize {
+            return Err(Error::ConnectionCountMismatch {
+                expected: self.header.conn_count,
+                actual: res.len(),
+            });
+        }
+        Ok(res)
+    }
+
+    /// Get message definition and MD5 sum of the message type used on `topic`.
+    ///
+    /// Topic is matched against [`Connection::storage_topic`] of connections
+    /// stored in the index section, or in chunks if the index is not
+    /// available. Returns `None` if no such connection exists.
+    pub fn topic_schema(&self, topic: &str) -> Result<Option<(String, [u8; 16])>> {
+        self.find_connection(topic, |conn| {
+            (conn.message_definition.to_string(), conn.md5sum)
+        })
+    }
+
+    /// Check if messages on `topic` were published in the latching mode.
+    ///
+    /// Topic is matched against [`Connection::storage_topic`] of connections
+    /// stored in the index section, or in chunks if the index is not
+    /// available. Returns `None` if no such connection exists.
+    pub fn is_topic_latched(&self, topic: &str) -> Result<Option<bool>> {
+        self.find_connection(topic, |conn| conn.latching)
+    }
+
+    /// Get distinct pairs of message type and its MD5 sum used by
+    /// connections stored in the index section, or in chunks if the index
+    /// is not available.
+    ///
+    /// Pairs are sorted by message type.
+    pub fn message_types(&self) -> Result<Vec<(String, [u8; 16])>> {
+        let mut res = BTreeSet::new();
+        self.visit_connections(|conn| {
+            res.insert((conn.tp.to_string(), conn.md5sum));
+        })?;
+        Ok(res.into_iter().collect())
+    }
+
+    /// Get distinct non-empty caller IDs (i.e. names of publishing nodes)
+    /// of connections stored in the index section, or in chunks if the
+    /// index is not available.
+    ///
+    /// IDs are sorted alphabetically.
+    pub fn caller_ids(&self) -> Result<Vec<String>> {
+        let mut res = BTreeSet::new();
+        self.visit_connections(|conn| {
+            if !conn.caller_id.is_empty() {
+                res.insert(conn.caller_id.to_string());
+            }
+        })?;
+        Ok(res.into_iter().collect())
+    }
+
+    /// Get message types which are used with more than one MD5 sum.
+    ///
+    /// Such conflicts usually mean that the bag was recorded from nodes built
+    /// with different versions of message definitions.
+    pub fn message_type_conflicts(&self) -> Result<Vec<String>> {
+        let mut res: Vec<String> = Vec::new();
+        let types = self.message_types()?;
+        for pair in types.windows(2) {
+            let tp = &pair[0].0;
+            if *tp == pair[1].0 && res.last() != Some(tp) {
+                res.push(tp.clone());
+            }
+        }
+        Ok(res)
+    }
+
+    /// Call `f` for every `Connection` record stored in the bag file.
+    ///
+    /// Unlike [`RosBag::index_records`], this also visits connection records
+    /// stored inside chunks, so the same logical connection usually gets
+    /// reported several times. Records in the chunk section are visited
+    /// first, followed by ones in the index section.
+    ///
+    /// Connections are passed by reference since connections inside
+    /// compressed chunks borrow decompressed chunk data.
+    pub fn for_each_connection<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Connection<'_>),
+    {
+        self.visit_chunk_connections(&mut f)?;
+        for record in self.index_records() {
+            if let IndexRecord::Connection(conn) = record? {
+                f(&conn);
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` for every message in the bag file together with timestamp
+    /// stored in its leading `std_msgs/Header`.
+    ///
+    /// Timestamp is `None` for messages without a header, see
+    /// [`MessageData::peek_header`]. Connections are looked up in the index
+    /// section (or among all connection records in chunks if the index is
+    /// not available) first and then among connection records preceding the
+    /// message in its chunk. Messages are visited in the order they are stored.
+    pub fn for_each_message_with_stamp<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(MessageData<'_>, Option<u64>),
+    {
+        let mut buf = Vec::new();
+        let index_conns = self.connection_map(&mut buf)?;
+        for record in self.chunk_records() {
+            let chunk = match record? {
+                ChunkRecord::Chunk(chunk) => chunk,
+                ChunkRecord::IndexData(_) => continue,
+            };
+            let mut chunk_conns = HashMap::new();
+            for msg in chunk.messages() {
+                match msg? {
+                    MessageRecord::Connection(conn) => {
+                        chunk_conns.insert(conn.id, conn);
+                    }
+                    MessageRecord::MessageData(msg) => {
+                        let conn = index_conns
+                            .get(&msg.conn_id)
+                            .or_else(|| chunk_conns.get(&msg.conn_id))
+                            .ok_or(Error::InvalidRecord)?;
+                        let stamp = msg.peek_header(conn)?.map(|h| h.stamp);
+                        f(msg, stamp);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` for the first `n` messages stored in the bag file.
+    ///
+    /// Chunks are decompressed only until `n` messages were visited, which
+    /// makes it cheap to peek at the beginning of a big bag. Returns number
+    /// of visited messages, which is smaller than `n` if the bag contains
+    /// fewer messages.
+    pub fn take_messages<F>(&self, n: usize, mut f: F) -> Result<usize>
+    where
+        F: FnMut(MessageData<'_>),
+    {
+        let mut count = 0;
+        let mut records = self.chunk_records();
+        while count < n {
+            let chunk = match records.next().transpose()? {
+                Some(ChunkRecord::Chunk(chunk)) => chunk,
+                Some(ChunkRecord::IndexData(_)) => continue,
+                None => break,
+            };
+            for msg in chunk.messages() {
+                if let MessageRecord::MessageData(msg) = msg? {
+                    f(msg);
+                    count += 1;
+                    if count == n {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Route messages to per-connection sinks.
+    ///
+    /// `make_sink` gets called once for every connection stored in the index
+    /// section (or inside chunks for connections missing from the index) and
+    /// returns a sink which receives all messages of this connection, or
+    /// `None` to drop them. Messages are passed to sinks in the order they
+    /// are stored in the file.
+    pub fn demux<F, W>(&self, mut make_sink: F) -> Result<()>
+    where
+        F: FnMut(&Connection<'_>) -> Option<W>,
+        W: FnMut(MessageData<'_>),
+    {
+        let mut sinks: HashMap<u32, Option<W>> = HashMap::new();
+        for record in self.index_records() {
+            if let IndexRecord::Connection(conn) = record? {
+                sinks.entry(conn.id).or_insert_with(|| make_sink(&conn));
+            }
+        }
+        for record in self.chunk_records() {
+            let chunk = match record? {
+                ChunkRecord::Chunk(chunk) => chunk,
+                ChunkRecord::IndexData(_) => continue,
+            };
+            for msg in chunk.messages() {
+                match msg? {
+                    MessageRecord::Connection(conn) => {
+                        sinks.entry(conn.id).or_insert_with(|| make_sink(&conn));
+                    }
+                    MessageRecord::MessageData(msg) => {
+                        let sink = sinks.get_mut(&msg.conn_id).ok_or(Error::InvalidRecord)?;
+                        if let Some(sink) = sink {
+                            sink(msg);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get time range of messages stored in the bag file as
+    /// `(start_time, end_time)` in nanoseconds of UNIX epoch.
+    ///
+    /// The range is computed from `ChunkInfo` records in the index section.
+    /// Returns `None` if the bag file does not contain any chunks.
+    pub fn time_range(&self) -> Result<Option<(u64, u64)>> {
+        self.require_index()?;
+        let mut res: Option<(u64, u64)> = None;
+        for record in self.index_records() {
+            if let IndexRecord::ChunkInfo(info) = record? {
+                res = Some(match res {
+                    Some((start, end)) => (start.min(info.start_time), end.max(info.end_time)),
+                    None => (info.start_time, info.end_time),
+                });
+            }
+        }
+        Ok(res)
+    }
+
+    /// Get time range of messages using only the first and the last
+    /// `ChunkInfo` records in the index section.
+    ///
+    /// Assumes the layout written by the ROS bag recorder: connection
+    /// records, whose number is stored in the bag header, followed by
+    /// `ChunkInfo` records ordered by time. Connection records and
+    /// `ChunkInfo` records between the first and the last one are skipped
+    /// using their length prefixes without being parsed. If `verify` is
+    /// `true`, the result of a full scan by [`RosBag::time_range`] is
+    /// returned instead.
+    pub fn time_range_fast(&self, verify: bool) -> Result<Option<(u64, u64)>> {
+        if verify {
+            return self.time_range();
         }
+        self.require_index()?;
+        let mut cursor = self.cursor(self.index_pos, self.index_end());
+        for _ in 0..self.header.conn_count {
+            record::skip_record(&mut cursor)?;
+        }
+        if cursor.left() == 0 {
+            return Ok(None);
+        }
+        let first = cursor.pos();
+        let mut last = first;
+        while cursor.left() != 0 {
+            last = cursor.pos();
+            record::skip_record(&mut cursor)?;
+        }
+        let mut chunk_info_at = |pos| -> Result<ChunkInfo<'_>> {
+            cursor.seek(pos)?;
+            match Record::next_record(&mut cursor)? {
+                Record::ChunkInfo(info) => Ok(info),
+                v => Err(Error::UnexpectedIndexSectionRecord(v.kind())),
+            }
+        };
+        let start = chunk_info_at(first)?.start_time;
+        let end = chunk_info_at(last)?.end_time;
+        Ok(Some((start, end)))
+    }
+
+    /// Compute fingerprint of the bag file suitable for cache invalidation.
+    ///
+    /// The fingerprint is a 64-bit FNV-1a hash of the file size, bag header
+    /// fields and the overall time range of chunks listed in the index
+    /// section, so it can be computed without reading chunk data. Its value
+    /// is stable across crate versions and platforms.
+    pub fn index_fingerprint(&self) -> Result<u64> {
+        let (start_time, end_time) = self.time_range()?.unwrap_or((0, 0));
+
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut update = |v: u64| {
+            for b in v.to_le_bytes() {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        update(self.data.len() as u64);
+        update(self.index_pos as u64);
+        update(self.header.conn_count as u64);
+        update(self.header.chunk_count as u64);
+        update(start_time);
+        update(end_time);
+        Ok(hash)
+    }
+
+    /// Return [`Error::MissingIndex`] if the index section is not available.
+    fn require_index(&self) -> Result<()> {
+        if self.has_index {
+            Ok(())
+        } else {
+            Err(Error::MissingIndex)
+        }
+    }
+
+    /// Get end position of the index section, equal to `index_pos` if the
+    /// index is not available.
+    fn index_end(&self) -> usize {
+        if self.has_index {
+            self.data.len()
+        } else {
+            self.index_pos
+        }
+    }
+
+    /// Get cursor over `data[start..end]` with the configured record size
+    /// limit and decompressors.
+    fn cursor(&self, start: usize, end: usize) -> Cursor<'_> {
+        Cursor::new(&self.data[start..end])
+            .with_file_offset(start as u64)
+            .with_limit(self.max_record_size)
+            .with_decompressors(&self.decompressors)
+            .with_unknown_field_policy(self.unknown_fields)
+    }
+
+    /// Call `f` for every connection stored in the index section, or for
+    /// every connection record stored in chunks if the index is not
+    /// available.
+    fn visit_connections<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Connection<'_>),
+    {
+        if self.has_index {
+            for record in self.index_records() {
+                if let IndexRecord::Connection(conn) = record? {
+                    f(&conn);
+                }
+            }
+            return Ok(());
+        }
+        self.visit_chunk_connections(&mut f)
+    }
+
+    /// Call `f` for every connection record stored in chunks.
+    fn visit_chunk_connections<F>(&self, f: &mut F) -> Result<()>
+    where
+        F: FnMut(&Connection<'_>),
+    {
+        for record in self.chunk_records() {
+            if let ChunkRecord::Chunk(chunk) = record? {
+                for msg in chunk.messages() {
+                    if let MessageRecord::Connection(conn) = msg? {
+                        f(&conn);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get connections keyed by their ID.
+    ///
+    /// Connections are read from the index section, or from chunks if the
+    /// index is not available. In the latter case connection records are
+    /// copied into `buf`, since chunk data does not outlive the chunks.
+    fn connection_map<'a>(&'a self, buf: &'a mut Vec<u8>) -> Result<HashMap<u32, Connection<'a>>> {
+        let mut res = HashMap::new();
+        if self.has_index {
+            for record in self.index_records() {
+                if let IndexRecord::Connection(conn) = record? {
+                    res.insert(conn.id, conn);
+                }
+            }
+            return Ok(res);
+        }
+        let mut ids = HashSet::new();
+        self.visit_connections(|conn| {
+            if ids.insert(conn.id) {
+                conn.write_record(buf);
+            }
+        })?;
+        let mut records = MessageRecordsIterator::new(buf);
+        records.cursor = records
+            .cursor
+            .with_unknown_field_policy(self.unknown_fields);
+        for record in records {
+            if let MessageRecord::Connection(conn) = record? {
+                res.insert(conn.id, conn);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Call `f` with the first connection stored under `topic`.
+    fn find_connection<T, F>(&self, topic: &str, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&Connection<'_>) -> T,
+    {
+        let mut f = Some(f);
+        let mut res = None;
+        self.visit_connections(|conn| {
+            if conn.storage_topic == topic {
+                if let Some(f) = f.take() {
+                    res = Some(f(conn));
+                }
+            }
+        })?;
+        Ok(res)
     }
 }
@@ -0,0 +1,104 @@
+use crate::record::next_record_bytes;
+use crate::record_types::{Connection, MessageData, RecordGen};
+use crate::{
+    ChunkRecord, MessageRecord, MessageRecordsIterator, Result, RosBag, UnknownFieldPolicy,
+};
+use std::collections::HashMap;
+
+/// Bag file content loaded into memory.
+///
+/// Created by [`RosBag::load_all`]. Holds decompressed data of all chunks
+/// and connection records from the index section, so iteration does not
+/// touch the bag file and does not repeat decompression.
+#[derive(Debug, Clone)]
+pub struct LoadedBag {
+    chunks: Vec<Vec<u8>>,
+    connections: Vec<u8>,
+    unknown_fields: UnknownFieldPolicy,
+}
+
+impl LoadedBag {
+    /// Get iterator over messages stored in all chunks.
+    ///
+    /// Messages are yielded in the order in which they are stored in the
+    /// bag file. Iteration stops after the first error.
+    pub fn messages(&self) -> impl Iterator<Item = Result<MessageData<'_>>> + '_ {
+        let mut failed = false;
+        self.chunks
+            .iter()
+            .flat_map(move |data| self.records(data))
+            .filter_map(|record| match record {
+                Ok(MessageRecord::MessageData(msg)) => Some(Ok(msg)),
+                Ok(MessageRecord::Connection(_)) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .take_while(move |res| !std::mem::replace(&mut failed, res.is_err()))
+    }
+
+    /// Get messages grouped by connection ID.
+    ///
+    /// Messages of each connection are stored in the order in which they
+    /// are stored in the bag file. All messages borrow the loaded data, see
+    /// [`RosBag::messages_grouped`] for a variant which does not require
+    /// loading the whole bag into memory.
+    pub fn messages_by_connection(&self) -> Result<HashMap<u32, Vec<MessageData<'_>>>> {
+        let mut res: HashMap<u32, Vec<MessageData<'_>>> = HashMap::new();
+        for msg in self.messages() {
+            let msg = msg?;
+            res.entry(msg.conn_id).or_default().push(msg);
+        }
+        Ok(res)
+    }
+
+    /// Get iterator over connections stored in the index section.
+    pub fn connections(&self) -> impl Iterator<Item = Result<Connection<'_>>> + '_ {
+        self.records(&self.connections)
+            .filter_map(|record| match record {
+                Ok(MessageRecord::Connection(conn)) => Some(Ok(conn)),
+                Ok(MessageRecord::MessageData(_)) => None,
+                Err(err) => Some(Err(err)),
+            })
+    }
+
+    /// Get number of loaded chunks.
+    pub fn get_chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn records<'a>(&self, data: &'a [u8]) -> MessageRecordsIterator<'a> {
+        let mut iter = MessageRecordsIterator::new(data);
+        iter.cursor = iter.cursor.with_unknown_field_policy(self.unknown_fields);
+        iter
+    }
+}
+
+impl RosBag {
+    /// Load all chunks and connections into memory.
+    ///
+    /// All chunks are decompressed upfront, so memory usage is
+    /// proportional to the uncompressed size of the bag file. Intended for
+    /// small bags which get iterated over many times, e.g. in tests.
+    pub fn load_all(&self) -> Result<LoadedBag> {
+        let mut chunks = Vec::with_capacity(self.header.chunk_count as usize);
+        for record in self.chunk_records() {
+            if let ChunkRecord::Chunk(chunk) = record? {
+                chunks.push(chunk.into_data());
+            }
+        }
+
+        let mut connections = Vec::new();
+        let mut cursor = self.cursor(self.index_pos, self.index_end());
+        while cursor.left() != 0 {
+            let (op, buf) = next_record_bytes(&mut cursor)?;
+            if op == Connection::OP {
+                connections.extend_from_slice(buf);
+            }
+        }
+
+        Ok(LoadedBag {
+            chunks,
+            connections,
+            unknown_fields: self.unknown_fields,
+        })
+    }
+}
@@ -0,0 +1,270 @@
+use crate::record::{next_raw_record, read_op, skip_unknown_record, Record};
+use crate::record_types::{Chunk, ChunkInfo, Connection, IndexData, MessageData, RecordGen};
+use crate::{record, Error, IndexRecord, MessageRecord, Result, RosBag};
+
+/// Location of a message in the bag file built from index records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLocator {
+    /// Position of the chunk record which contains the message
+    pub chunk_pos: u64,
+    /// Offset of the message data record in uncompressed chunk data
+    pub offset: u32,
+    /// ID of the message connection
+    pub conn_id: u32,
+    /// Time at which the message was received in nanoseconds of UNIX epoch
+    pub time: u64,
+}
+
+impl RosBag {
+    /// Get locations of all messages stored in the bag file.
+    ///
+    /// Locations are built from `IndexData` records in the chunk section
+    /// without decompressing chunks. `IndexData` records are associated with
+    /// the closest preceding `Chunk` record, as done by the ROS bag writer
+    /// which stores index of a chunk right after it.
+    pub fn index_entries(&self) -> Result<Vec<MessageLocator>> {
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        let mut chunk_pos = None;
+        let mut res = Vec::new();
+        while cursor.left() != 0 {
+            let pos = self.start_pos as u64 + cursor.pos();
+            let header = cursor.next_chunk()?;
+            match read_op(header)? {
+                Chunk::OP => {
+                    chunk_pos = Some(pos);
+                    cursor.next_chunk()?;
+                }
+                IndexData::OP => {
+                    let chunk_pos = chunk_pos.ok_or(Error::InvalidRecord)?;
+                    let index_data = IndexData::read(header, &mut cursor)?;
+                    push_locators(&mut res, chunk_pos, &index_data);
+                }
+                op if skip_unknown_record(op, false) => {
+                    cursor.next_chunk()?;
+                }
+                op => {
+                    let t = record::op_type(op).ok_or(Error::UnknownRecord(op))?;
+                    return Err(Error::UnexpectedChunkSectionRecord(t));
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Get locations of messages received in the `[start_ns, end_ns]` time
+    /// range.
+    ///
+    /// `ChunkInfo` records are used to select chunks which may contain such
+    /// messages, so only `IndexData` records of those chunks get read.
+    /// Returned locations are ordered by chunk position and connection.
+    pub fn message_locators_in_range(
+        &self,
+        start_ns: u64,
+        end_ns: u64,
+    ) -> Result<Vec<MessageLocator>> {
+        self.require_index()?;
+        let mut chunks = Vec::new();
+        for record in self.index_records() {
+            if let IndexRecord::ChunkInfo(info) = record? {
+                if info.start_time <= end_ns && info.end_time >= start_ns {
+                    chunks.push(info.chunk_pos);
+                }
+            }
+        }
+        chunks.sort_unstable();
+
+        let mut res = Vec::new();
+        for chunk_pos in chunks {
+            self.chunk_locators(chunk_pos, &mut res)?;
+        }
+        res.retain(|l| start_ns <= l.time && l.time <= end_ns);
+        Ok(res)
+    }
+
+    /// Find `ChunkInfo` record of the chunk which occupies file position
+    /// `pos`.
+    ///
+    /// Extents of chunks are computed from lengths stored in chunk records,
+    /// so chunks do not get decompressed. Returns `None` if `pos` is not
+    /// inside any chunk listed in the index section.
+    pub fn chunk_at_position(&self, pos: u64) -> Result<Option<ChunkInfo<'_>>> {
+        self.require_index()?;
+        for record in self.index_records() {
+            let info = match record? {
+                IndexRecord::ChunkInfo(info) => info,
+                _ => continue,
+            };
+            if pos < info.chunk_pos {
+                continue;
+            }
+            let mut cursor = self.cursor(self.start_pos, self.index_pos);
+            let start = info
+                .chunk_pos
+                .checked_sub(self.start_pos as u64)
+                .ok_or(Error::OutOfBounds)?;
+            cursor.seek(start)?;
+            next_raw_record(&mut cursor)?;
+            if pos < self.start_pos as u64 + cursor.pos() {
+                return Ok(Some(info));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Call `f` for messages grouped by connection.
+    ///
+    /// Connections are visited in the order in which they are stored in the
+    /// index section and all messages of a connection are passed to `f`
+    /// before moving to the next one. Messages are located using `IndexData`
+    /// records, so only one chunk is kept in memory at a time, but a chunk
+    /// gets decompressed once for every connection with messages in it.
+    /// Use [`LoadedBag::messages_by_connection`][crate::LoadedBag::messages_by_connection]
+    /// to decompress every chunk only once at the cost of keeping all of
+    /// them in memory.
+    pub fn messages_grouped<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Connection<'_>, MessageData<'_>),
+    {
+        let mut locators = self.index_entries()?;
+        locators.sort_by_key(|l| (l.conn_id, l.chunk_pos, l.offset));
+        for conn in self.index_connections()? {
+            let start = locators.partition_point(|l| l.conn_id < conn.id);
+            let end = locators.partition_point(|l| l.conn_id <= conn.id);
+            for group in group_by_chunk(&locators[start..end]) {
+                let chunk = self.chunk_record_at(group[0].chunk_pos)?;
+                let mut iter = chunk.messages();
+                for locator in group {
+                    iter.seek(locator.offset)?;
+                    match iter.next().ok_or(Error::InvalidRecord)?? {
+                        MessageRecord::MessageData(msg) => f(&conn, msg),
+                        MessageRecord::Connection(_) => return Err(Error::InvalidRecord),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the `n`-th (counting from zero) message stored on
+    /// `topic` and return its result.
+    ///
+    /// Topic is matched against [`Connection::storage_topic`] of connections
+    /// stored in the index section. Message counts from `ChunkInfo` records
+    /// are used to find the chunk which contains the message, so only
+    /// `IndexData` records of this chunk get read and only this chunk gets
+    /// decompressed. Messages are counted in the order of chunk positions
+    /// and by time within a chunk, which matches time order for sequentially
+    /// recorded bags. Returns `None` if `n` is out of range.
+    pub fn nth_message<F, T>(&self, topic: &str, n: usize, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(MessageData<'_>) -> T,
+    {
+        self.require_index()?;
+        let mut conn_ids = Vec::new();
+        let mut chunks = Vec::new();
+        for record in self.index_records() {
+            match record? {
+                IndexRecord::Connection(conn) if conn.storage_topic == topic => {
+                    conn_ids.push(conn.id);
+                }
+                IndexRecord::ChunkInfo(info) => chunks.push(info),
+                _ => {}
+            }
+        }
+        chunks.sort_by_key(|info| info.chunk_pos);
+
+        let mut n = n as u64;
+        for info in chunks {
+            let count: u64 = info
+                .entries()
+                .filter(|e| conn_ids.contains(&e.conn_id))
+                .map(|e| e.count as u64)
+                .sum();
+            if n >= count {
+                n -= count;
+                continue;
+            }
+            let mut locators = Vec::new();
+            self.chunk_locators(info.chunk_pos, &mut locators)?;
+            locators.retain(|l| conn_ids.contains(&l.conn_id));
+            locators.sort_by_key(|l| (l.time, l.offset));
+            let locator = locators.get(n as usize).ok_or(Error::InvalidRecord)?;
+
+            let chunk = self.chunk_record_at(info.chunk_pos)?;
+            let mut iter = chunk.messages();
+            iter.seek(locator.offset)?;
+            return match iter.next().ok_or(Error::InvalidRecord)?? {
+                MessageRecord::MessageData(msg) => Ok(Some(f(msg))),
+                MessageRecord::Connection(_) => Err(Error::InvalidRecord),
+            };
+        }
+        Ok(None)
+    }
+
+    /// Read `Chunk` record at file position `pos`.
+    pub(crate) fn chunk_record_at(&self, pos: u64) -> Result<Chunk<'_>> {
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        let pos = pos
+            .checked_sub(self.start_pos as u64)
+            .ok_or(Error::OutOfBounds)?;
+        cursor.seek(pos)?;
+        match Record::next_record(&mut cursor)? {
+            Record::Chunk(chunk) => Ok(chunk),
+            v => Err(Error::UnexpectedChunkSectionRecord(v.kind())),
+        }
+    }
+
+    /// Append locations from `IndexData` records which follow chunk at
+    /// `chunk_pos` to `res`.
+    pub(crate) fn chunk_locators(
+        &self,
+        chunk_pos: u64,
+        res: &mut Vec<MessageLocator>,
+    ) -> Result<()> {
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        if chunk_pos < self.start_pos as u64 {
+            return Err(Error::OutOfBounds);
+        }
+        cursor.seek(chunk_pos - self.start_pos as u64)?;
+        let (op, _, _) = next_raw_record(&mut cursor)?;
+        if op != Chunk::OP {
+            return Err(Error::InvalidRecord);
+        }
+        while cursor.left() != 0 {
+            let header = cursor.clone().next_chunk()?;
+            match read_op(header)? {
+                IndexData::OP => {}
+                op if skip_unknown_record(op, false) => {
+                    next_raw_record(&mut cursor)?;
+                    continue;
+                }
+                _ => break,
+            }
+            cursor.next_chunk()?;
+            let index_data = IndexData::read(header, &mut cursor)?;
+            push_locators(res, chunk_pos, &index_data);
+        }
+        Ok(())
+    }
+}
+
+/// Split locators sorted by chunk position into groups with equal positions.
+fn group_by_chunk(mut locators: &[MessageLocator]) -> Vec<&[MessageLocator]> {
+    let mut res = Vec::new();
+    while let Some(first) = locators.first() {
+        let n = locators.partition_point(|l| l.chunk_pos == first.chunk_pos);
+        let (group, rest) = locators.split_at(n);
+        res.push(group);
+        locators = rest;
+    }
+    res
+}
+
+fn push_locators(res: &mut Vec<MessageLocator>, chunk_pos: u64, index_data: &IndexData<'_>) {
+    res.extend(index_data.entries().map(|e| MessageLocator {
+        chunk_pos,
+        offset: e.offset,
+        conn_id: index_data.conn_id,
+        time: e.time,
+    }));
+}
@@ -1,9 +1,12 @@
 //! Iterators over content of `Chunk`
 use super::Result;
-use crate::record_types::{Connection, MessageData};
-use crate::{record::Record, Error};
+use crate::record::{check_record_at, skip_unknown_record, Record};
+use crate::record_types::{Connection, MessageData, RecordGen};
+use crate::Error;
 
 use crate::cursor::Cursor;
+use std::collections::HashMap;
+use std::iter::FusedIterator;
 
 /// Record types which can be stored in a [`Chunk`][crate::record_types::Chunk] record.
 #[derive(Debug, Clone)]
@@ -15,8 +18,13 @@ pub enum MessageRecord<'a> {
 }
 
 /// Iterator over records stored in a [`Chunk`][crate::record_types::Chunk] record.
+///
+/// Iteration stops after the first error, but it can be resumed with `seek`
+/// or continued automatically using [`lenient`][Self::lenient] mode.
 pub struct MessageRecordsIterator<'a> {
     pub(crate) cursor: Cursor<'a>,
+    pub(crate) skip_unknown: bool,
+    pub(crate) lenient: bool,
 }
 
 impl<'a> MessageRecordsIterator<'a> {
@@ -27,6 +35,8 @@ impl<'a> MessageRecordsIterator<'a> {
         );
         Self {
             cursor: Cursor::new(data),
+            skip_unknown: false,
+            lenient: false,
         }
     }
 
@@ -39,21 +49,188 @@ impl<'a> MessageRecordsIterator<'a> {
     pub fn seek(&mut self, offset: u32) -> Result<()> {
         Ok(self.cursor.seek(offset as u64)?)
     }
+
+    /// Seek to an offset after checking that it points to a `MessageData` or
+    /// `Connection` record.
+    ///
+    /// On error the iterator position is left unchanged.
+    pub fn seek_validated(&mut self, offset: u32) -> Result<()> {
+        let ops = [MessageData::OP, Connection::OP];
+        let unexpected = Error::UnexpectedMessageRecord;
+        check_record_at(&self.cursor, offset as u64, &ops, unexpected)?;
+        Ok(self.cursor.seek(offset as u64)?)
+    }
+
+    /// Skip records with unknown `op` values instead of returning an error.
+    ///
+    /// Skipped records are reported using `log::warn!`. Useful for reading
+    /// files which contain vendor-specific record types. Legacy message
+    /// definition records (`op=0x01`) are always skipped. Default: `false`.
+    pub fn skip_unknown(mut self, skip: bool) -> Self {
+        self.skip_unknown = skip;
+        self
+    }
+
+    /// Continue iteration after records which can not be parsed.
+    ///
+    /// After an error the iterator searches forward for the next position
+    /// which looks like a beginning of `MessageData` or `Connection` record,
+    /// i.e. has a valid `op` field and header and data lengths which fit into
+    /// the chunk. Resynchronization is best-effort: records can be missed and
+    /// corrupted data may be mistaken for a record. Default: `false`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Move cursor to the next plausible record start after `pos` or to the
+    /// end of data if there is none.
+    fn resync(&mut self, pos: u64) {
+        let ops = [MessageData::OP, Connection::OP];
+        let unexpected = Error::UnexpectedMessageRecord;
+        let end = pos + self.cursor.left();
+        self.cursor.finish();
+        for next in pos + 1..end {
+            if check_record_at(&self.cursor, next, &ops, unexpected).is_ok() {
+                log::warn!("skipped {} bytes of corrupted chunk data", next - pos);
+                self.cursor.seek(next).expect("position was checked");
+                return;
+            }
+        }
+    }
+
+    /// Attach to each message the `Connection` record with matching ID which
+    /// precedes it in the chunk.
+    ///
+    /// Messages whose connection is not stored in the chunk (e.g. it is
+    /// present only in the index section) get `None`.
+    pub fn resolve_connections(self) -> ResolvedMessages<'a> {
+        ResolvedMessages {
+            inner: self,
+            conns: HashMap::new(),
+        }
+    }
 }
 
 impl<'a> Iterator for MessageRecordsIterator<'a> {
     type Item = Result<MessageRecord<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cursor.left() == 0 {
-            return None;
+        loop {
+            if self.cursor.left() == 0 {
+                return None;
+            }
+            let pos = self.cursor.pos();
+            let res = match Record::next_record(&mut self.cursor) {
+                Err(Error::UnknownRecord(op)) if skip_unknown_record(op, self.skip_unknown) => {
+                    continue
+                }
+                Ok(Record::MessageData(v)) => Ok(MessageRecord::MessageData(v)),
+                Ok(Record::Connection(v)) => Ok(MessageRecord::Connection(v)),
+                Ok(v) => Err(Error::UnexpectedMessageRecord(v.kind())),
+                Err(e) => Err(e),
+            };
+            if res.is_err() {
+                if self.lenient {
+                    self.resync(pos);
+                } else {
+                    self.cursor.finish();
+                }
+            }
+            return Some(res);
+        }
+    }
+}
+
+/// Filter which reduces rate of messages on every connection.
+///
+/// A message is kept if at least `1 / target_hz` seconds have elapsed since
+/// the last kept message of the same connection, based on the message
+/// `time` field. State is tracked per connection, so interleaved topics are
+/// decimated independently and one filter can be used across all chunks of
+/// a bag.
+///
+/// # Example
+/// ```
+/// use rosbag::{ChunkRecord, Decimator, MessageRecord, RosBag};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = "dummy.bag";
+/// let bag = RosBag::new(path)?;
+/// let mut decimator = Decimator::new(10.0);
+/// for record in bag.chunk_records() {
+///     if let ChunkRecord::Chunk(chunk) = record? {
+///         for msg in chunk.messages() {
+///             if let MessageRecord::MessageData(msg) = msg? {
+///                 if decimator.keep(&msg) {
+///                     // ..
+///                 }
+///             }
+///         }
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    interval: u64,
+    last: HashMap<u32, u64>,
+}
+
+impl Decimator {
+    /// Create filter which keeps at most `target_hz` messages per second on
+    /// every connection.
+    ///
+    /// # Panics
+    /// If `target_hz` is not a positive finite number.
+    pub fn new(target_hz: f64) -> Self {
+        assert!(
+            target_hz.is_finite() && target_hz > 0.0,
+            "target rate must be positive"
+        );
+        Self {
+            interval: (1e9 / target_hz) as u64,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Check if `msg` should be kept and update the connection state if so.
+    pub fn keep(&mut self, msg: &MessageData<'_>) -> bool {
+        match self.last.get(&msg.conn_id) {
+            Some(&last) if msg.time.saturating_sub(last) < self.interval => false,
+            _ => {
+                self.last.insert(msg.conn_id, msg.time);
+                true
+            }
+        }
+    }
+}
+
+/// Iterator adapter which attaches connections to messages.
+///
+/// Created by [`MessageRecordsIterator::resolve_connections`].
+pub struct ResolvedMessages<'a> {
+    inner: MessageRecordsIterator<'a>,
+    conns: HashMap<u32, Connection<'a>>,
+}
+
+impl<'a> Iterator for ResolvedMessages<'a> {
+    type Item = Result<(Option<Connection<'a>>, MessageData<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok(MessageRecord::MessageData(msg)) => {
+                    let conn = self.conns.get(&msg.conn_id).cloned();
+                    return Some(Ok((conn, msg)));
+                }
+                Ok(MessageRecord::Connection(conn)) => {
+                    self.conns.insert(conn.id, conn);
+                }
+                Err(err) => return Some(Err(err)),
+            }
         }
-        let res = match Record::next_record(&mut self.cursor) {
-            Ok(Record::MessageData(v)) => Ok(MessageRecord::MessageData(v)),
-            Ok(Record::Connection(v)) => Ok(MessageRecord::Connection(v)),
-            Ok(v) => Err(Error::UnexpectedMessageRecord(v.get_type())),
-            Err(e) => Err(e),
-        };
-        Some(res)
     }
 }
+
+impl<'a> FusedIterator for ResolvedMessages<'a> {}
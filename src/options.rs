@@ -0,0 +1,207 @@
+use crate::record_types::chunk::Decompressors;
+use crate::record_types::Decompressor;
+use crate::storage::Storage;
+use crate::{parse_bag_header, Error, RosBag};
+use memmap2::Mmap;
+use std::io::Read;
+use std::{fs, io, path::Path, sync::Arc};
+
+/// Handling of record header fields which are not known to the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownFieldPolicy {
+    /// Silently ignore unknown fields.
+    Ignore,
+    /// Report unknown fields using `log::warn!`.
+    Warn,
+    /// Fail parsing with [`Error::UnknownField`].
+    Error,
+}
+
+impl Default for UnknownFieldPolicy {
+    fn default() -> Self {
+        UnknownFieldPolicy::Warn
+    }
+}
+
+/// Options which can be used to configure how a ROS bag file is opened.
+///
+/// # Example
+/// ```
+/// use rosbag::OpenOptions;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = "dummy.bag";
+/// let bag = OpenOptions::new().prefetch_index(true).open(path)?;
+/// # drop(bag);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    prefetch_index: bool,
+    max_record_size: u32,
+    decompressors: Decompressors,
+    tolerate_missing_index: bool,
+    unknown_fields: UnknownFieldPolicy,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            prefetch_index: false,
+            max_record_size: 1 << 31,
+            decompressors: Decompressors::default(),
+            tolerate_missing_index: false,
+            unknown_fields: UnknownFieldPolicy::Warn,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Create options with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask OS to start reading the index section in background while the
+    /// file gets opened.
+    ///
+    /// Useful when index records are accessed right after opening, e.g. to
+    /// get list of connections. Only has effect on Unix platforms.
+    /// Default: `false`.
+    pub fn prefetch_index(&mut self, prefetch: bool) -> &mut Self {
+        self.prefetch_index = prefetch;
+        self
+    }
+
+    /// Set maximum length of record headers and data (including decompressed
+    /// chunk data) accepted while iterating over the bag file.
+    ///
+    /// Records which exceed the limit result in [`Error::RecordTooLarge`][crate::Error::RecordTooLarge]
+    /// instead of trying to read or allocate the declared amount of data,
+    /// which is useful when processing untrusted or corrupted files.
+    /// Default: 2 GiB.
+    pub fn max_record_size(&mut self, size: u32) -> &mut Self {
+        self.max_record_size = size;
+        self
+    }
+
+    /// Register decompressor for chunks with the `compression` header field
+    /// equal to `name`.
+    ///
+    /// Chunks with such compression are reported as
+    /// [`Compression::Custom`][crate::record_types::Compression::Custom] and get
+    /// decompressed using `f`. Without a registered decompressor reading
+    /// them results in [`Error::UnsupportedCompression`][crate::Error::UnsupportedCompression].
+    /// Built-in compressions can not be overridden.
+    pub fn register_decompressor(&mut self, name: &str, f: Decompressor) -> &mut Self {
+        self.decompressors.insert(name, f);
+        self
+    }
+
+    /// Open files with missing or corrupted index section instead of
+    /// returning an error.
+    ///
+    /// Recorders which were killed mid-write leave `index_pos` in the bag
+    /// header unset or pointing to a truncated index. If the index position
+    /// is invalid, the chunk section is assumed to span up to the end of
+    /// file. If index records can not be parsed, the index section is treated
+    /// as empty. Use [`RosBag::has_index`] to check if the index is available.
+    /// Methods which look up connections (e.g. [`RosBag::topic_schema`])
+    /// fall back to scanning connection records stored in chunks, while
+    /// methods which rely on `ChunkInfo` or `IndexData` records (e.g.
+    /// [`RosBag::time_range`]) return [`Error::MissingIndex`][crate::Error::MissingIndex]
+    /// for such bags. Default: `false`.
+    pub fn tolerate_missing_index(&mut self, tolerate: bool) -> &mut Self {
+        self.tolerate_missing_index = tolerate;
+        self
+    }
+
+    /// Set handling of unknown fields in the bag header, record headers and
+    /// connection headers.
+    ///
+    /// Strict consumers can use [`UnknownFieldPolicy::Error`] to treat
+    /// unexpected fields as errors, while bulk processing of bags with benign
+    /// extra fields can use [`UnknownFieldPolicy::Ignore`] to silence
+    /// warnings. Default: [`UnknownFieldPolicy::Warn`].
+    pub fn unknown_field_policy(&mut self, policy: UnknownFieldPolicy) -> &mut Self {
+        self.unknown_fields = policy;
+        self
+    }
+
+    /// Open ROS bag file at `path` with the configured options.
+    ///
+    /// See [`RosBag::new`] for details about returned errors.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<RosBag> {
+        let data = unsafe { Mmap::map(&fs::File::open(path)?)? };
+        self.open_storage(Storage::Mmap(data))
+    }
+
+    /// Read ROS bag file of `size` bytes from `reader` into memory and open
+    /// it with the configured options.
+    ///
+    /// Useful for sources which can not be memory-mapped, e.g. members of
+    /// archive files. The whole file is kept in memory, so
+    /// [`prefetch_index`][Self::prefetch_index] has no effect.
+    pub fn open_reader<R: io::Read>(&self, reader: R, size: u64) -> io::Result<RosBag> {
+        let cap = size
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bag size is too big"))?;
+        let mut data = Vec::with_capacity(cap);
+        reader.take(size).read_to_end(&mut data)?;
+        if data.len() != cap {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.open_storage(Storage::Memory(data))
+    }
+
+    fn open_storage(&self, data: Storage) -> io::Result<RosBag> {
+        let invalid_data = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+        let (start_pos, header) =
+            parse_bag_header(&data, self.unknown_fields).map_err(invalid_data)?;
+
+        let mut has_index = true;
+        let index_pos = if start_pos <= header.index_pos && header.index_pos <= data.len() as u64 {
+            header.index_pos.try_into().unwrap()
+        } else if self.tolerate_missing_index {
+            log::warn!("invalid index position: {}", header.index_pos);
+            has_index = false;
+            data.len()
+        } else {
+            return Err(invalid_data(Error::InvalidIndexPosition {
+                index_pos: header.index_pos,
+                header_end: start_pos,
+                file_len: data.len() as u64,
+            }));
+        };
+        if header.chunk_count == 0 && index_pos as u64 > start_pos {
+            log::warn!("bag header declares zero chunks, but the chunk section is not empty");
+        }
+        #[cfg(unix)]
+        if let Storage::Mmap(mmap) = &data {
+            if self.prefetch_index && index_pos < data.len() {
+                let len = data.len() - index_pos;
+                if let Err(err) = mmap.advise_range(memmap2::Advice::WillNeed, index_pos, len) {
+                    log::warn!("failed to prefetch index section: {}", err);
+                }
+            }
+        }
+
+        let mut bag = RosBag {
+            data: Arc::new(data),
+            start_pos: start_pos.try_into().unwrap(),
+            index_pos,
+            header,
+            max_record_size: self.max_record_size,
+            decompressors: Arc::new(self.decompressors.clone()),
+            has_index,
+            unknown_fields: self.unknown_fields,
+        };
+        if bag.has_index && self.tolerate_missing_index {
+            if let Some(Err(err)) = bag.index_records().find(|r| r.is_err()) {
+                log::warn!("failed to read index section: {}", err);
+                bag.has_index = false;
+            }
+        }
+        Ok(bag)
+    }
+}
@@ -0,0 +1,162 @@
+use crate::record::{next_raw_record, Record};
+use crate::record_types::{Chunk, MessageData, RecordGen};
+use crate::{Error, MessageRecord, Result, RosBag};
+use std::collections::BTreeMap;
+use std::iter::FusedIterator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+type ChunkResults<T> = Vec<Result<T>>;
+
+/// Iterator over results of [`RosBag::process_parallel`].
+///
+/// Dropping the iterator stops worker threads after they finish processing
+/// their current chunks. Iteration stops after the first error.
+pub struct ParallelMessages<T> {
+    rx: mpsc::Receiver<(usize, ChunkResults<T>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    pending: BTreeMap<usize, ChunkResults<T>>,
+    current: std::vec::IntoIter<Result<T>>,
+    next_chunk: usize,
+    chunk_count: usize,
+    done: bool,
+}
+
+impl<T> Iterator for ParallelMessages<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if let Some(res) = self.current.next() {
+                self.done = res.is_err();
+                return Some(res);
+            }
+            if self.next_chunk == self.chunk_count {
+                self.done = true;
+                return None;
+            }
+            if let Some(results) = self.pending.remove(&self.next_chunk) {
+                self.current = results.into_iter();
+                self.next_chunk += 1;
+                continue;
+            }
+            match self.rx.recv() {
+                Ok((i, results)) => {
+                    self.pending.insert(i, results);
+                }
+                // all workers have exited without sending the next chunk,
+                // which is possible only if one of them has panicked
+                Err(_) => {
+                    self.done = true;
+                    for worker in self.workers.drain(..) {
+                        if let Err(err) = worker.join() {
+                            std::panic::resume_unwind(err);
+                        }
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl<T> FusedIterator for ParallelMessages<T> {}
+
+impl RosBag {
+    /// Apply `f` to all messages in the bag file using `concurrency` worker
+    /// threads.
+    ///
+    /// Chunks are distributed between workers, which decompress them and
+    /// apply `f` to their messages. Results are yielded in the order in which
+    /// messages are stored in the file. Results of chunks processed ahead of
+    /// the next yielded one are buffered, so a single slow chunk may increase
+    /// memory usage.
+    ///
+    /// # Panics
+    /// If `concurrency` is equal to zero. Panics in `f` are propagated to
+    /// the thread which iterates over results.
+    pub fn process_parallel<T, F>(&self, f: F, concurrency: usize) -> Result<ParallelMessages<T>>
+    where
+        T: Send + 'static,
+        F: Fn(MessageData<'_>) -> T + Send + Sync + 'static,
+    {
+        assert!(concurrency != 0, "concurrency must not be zero");
+        let positions = Arc::new(self.chunk_positions()?);
+        let next = Arc::new(AtomicUsize::new(0));
+        let f = Arc::new(f);
+        let (tx, rx) = mpsc::sync_channel(concurrency);
+
+        let workers = (0..concurrency.min(positions.len()))
+            .map(|_| {
+                let (bag, positions, next) = (self.clone(), positions.clone(), next.clone());
+                let (f, tx) = (f.clone(), tx.clone());
+                thread::spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let pos = match positions.get(i) {
+                        Some(&pos) => pos,
+                        None => break,
+                    };
+                    let results = bag.process_chunk_at(pos, &*f);
+                    if tx.send((i, results)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Ok(ParallelMessages {
+            rx,
+            workers,
+            pending: BTreeMap::new(),
+            current: Vec::new().into_iter(),
+            next_chunk: 0,
+            chunk_count: positions.len(),
+            done: false,
+        })
+    }
+
+    /// Get positions of all chunk records in the chunk section.
+    fn chunk_positions(&self) -> Result<Vec<u64>> {
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        let mut res = Vec::with_capacity(self.header.chunk_count as usize);
+        while cursor.left() != 0 {
+            let pos = self.start_pos as u64 + cursor.pos();
+            let (op, _, _) = next_raw_record(&mut cursor)?;
+            if op == Chunk::OP {
+                res.push(pos);
+            }
+        }
+        Ok(res)
+    }
+
+    fn process_chunk_at<T>(&self, pos: u64, f: &impl Fn(MessageData<'_>) -> T) -> Vec<Result<T>> {
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        let chunk = cursor
+            .seek(pos - self.start_pos as u64)
+            .map_err(Error::from)
+            .and_then(|()| match Record::next_record(&mut cursor)? {
+                Record::Chunk(chunk) => Ok(chunk),
+                v => Err(Error::UnexpectedChunkSectionRecord(v.kind())),
+            });
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => return vec![Err(err)],
+        };
+        let mut res = Vec::new();
+        for record in chunk.messages() {
+            match record {
+                Ok(MessageRecord::MessageData(msg)) => res.push(Ok(f(msg))),
+                Ok(MessageRecord::Connection(_)) => {}
+                Err(err) => {
+                    res.push(Err(err));
+                    break;
+                }
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,7 @@
+//! Re-exports of commonly used types.
+//!
+//! ```
+//! use rosbag::prelude::*;
+//! ```
+pub use crate::record_types::{Connection, MessageData};
+pub use crate::{ChunkRecord, IndexRecord, LoadedBag, MessageRecord, OpenOptions, RosBag};
@@ -0,0 +1,102 @@
+use crate::record::{next_raw_record, next_record_bytes, op_type};
+use crate::record_types::chunk::ChunkHeader;
+use crate::record_types::{Chunk, Compression, HeaderGen, IndexData, RecordGen};
+use crate::{Cursor, Error, Result};
+use std::iter::FusedIterator;
+
+/// Iterator over raw records stored after the bag header.
+///
+/// Yields position of each record in the file and its complete on-disk
+/// representation, i.e. length-prefixed header followed by length-prefixed
+/// data. Records are not parsed beyond reading their `op` field.
+///
+/// Iteration stops after the first error.
+pub struct RawRecordsIterator<'a> {
+    pub(crate) cursor: Cursor<'a>,
+    pub(crate) offset: u64,
+}
+
+impl<'a> Iterator for RawRecordsIterator<'a> {
+    type Item = Result<(u64, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor.left() == 0 {
+            return None;
+        }
+        let pos = self.offset + self.cursor.pos();
+        let res = next_record_bytes(&mut self.cursor).map(|(_, buf)| (pos, buf));
+        if res.is_err() {
+            self.cursor.finish();
+        }
+        Some(res)
+    }
+}
+
+impl<'a> FusedIterator for RawRecordsIterator<'a> {}
+
+/// `Chunk` record with its data left compressed.
+#[derive(Debug, Clone)]
+pub struct RawChunk<'a> {
+    /// Compression type for the data
+    pub compression: Compression,
+    /// Chunk data as stored in the file
+    pub compressed: &'a [u8],
+    /// Size of the data after decompression, as declared in the chunk header
+    pub uncompressed_size: u32,
+    /// Position of the chunk record in the file
+    pub chunk_pos: u64,
+}
+
+/// Iterator over chunks stored in the chunk section without decompressing
+/// their data.
+///
+/// Created by [`RosBag::raw_chunks`][crate::RosBag::raw_chunks]. `IndexData`
+/// records are skipped. Iteration stops after the first error.
+pub struct RawChunksIterator<'a> {
+    pub(crate) cursor: Cursor<'a>,
+    pub(crate) offset: u64,
+}
+
+impl<'a> RawChunksIterator<'a> {
+    fn next_chunk(&mut self) -> Result<Option<RawChunk<'a>>> {
+        let chunk_pos = self.offset + self.cursor.pos();
+        let (op, header, compressed) = next_raw_record(&mut self.cursor)?;
+        match op {
+            Chunk::OP => {}
+            IndexData::OP => return Ok(None),
+            op => {
+                return Err(match op_type(op) {
+                    Some(t) => Error::UnexpectedChunkSectionRecord(t),
+                    None => Error::UnknownRecord(op),
+                })
+            }
+        }
+        let header = ChunkHeader::read_header(header, self.cursor.unknown_field_policy())?;
+        Ok(Some(RawChunk {
+            compression: header.compression.ok_or(Error::InvalidHeader)?,
+            compressed,
+            uncompressed_size: header.size.ok_or(Error::InvalidHeader)?,
+            chunk_pos,
+        }))
+    }
+}
+
+impl<'a> Iterator for RawChunksIterator<'a> {
+    type Item = Result<RawChunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cursor.left() != 0 {
+            match self.next_chunk() {
+                Ok(None) => continue,
+                Ok(Some(chunk)) => return Some(Ok(chunk)),
+                Err(err) => {
+                    self.cursor.finish();
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a> FusedIterator for RawChunksIterator<'a> {}
@@ -4,46 +4,62 @@ use crate::cursor::Cursor;
 
 use crate::field_iter::FieldIterator;
 use crate::record_types::{Chunk, ChunkInfo, Connection, IndexData, MessageData, RecordGen};
+use crate::{ChunkRecord, IndexRecord, MessageRecord};
 
-/// Enum with all possible record variants
+/// `op` value of message definition records left over from bag format 1.x.
+pub(crate) const LEGACY_MSG_DEF_OP: u8 = 0x01;
+
+/// Enum with all possible record variants.
+///
+/// Records yielded by section iterators can be converted into this type
+/// using the `From` implementations. Records with other `op` values are
+/// reported as [`Error::UnknownRecord`], except for legacy message
+/// definition records (`op=0x01`) which are skipped by record iterators.
 #[derive(Debug, Clone)]
-pub(crate) enum Record<'a> {
+pub enum Record<'a> {
+    /// [`Chunk`] record.
     Chunk(Chunk<'a>),
+    /// [`Connection`] record.
     Connection(Connection<'a>),
+    /// [`MessageData`] record.
     MessageData(MessageData<'a>),
+    /// [`IndexData`] record.
     IndexData(IndexData<'a>),
+    /// [`ChunkInfo`] record.
     ChunkInfo(ChunkInfo<'a>),
 }
 
 impl<'a> Record<'a> {
+    /// Read next record, records with unknown `op` are skipped and reported
+    /// as [`Error::UnknownRecord`].
     pub(crate) fn next_record(c: &mut Cursor<'a>) -> Result<Self> {
+        let _span = span!(TRACE, "next_record", pos = c.pos());
+        c.check_bag_start()?;
         let header = c.next_chunk()?;
-
-        let mut op = None;
-        for item in FieldIterator::new(header) {
-            let (name, val) = item?;
-            if name == "op" {
-                if val.len() == 1 {
-                    op = Some(val[0]);
-                    break;
-                } else {
-                    return Err(Error::InvalidRecord);
-                }
+        // position after the data chunk which must be reached by the parser
+        let data_end = {
+            let mut data = c.clone();
+            data.next_u32()? as u64 + data.pos()
+        };
+        let record = match read_op(header)? {
+            IndexData::OP => Record::IndexData(IndexData::read(header, c)?),
+            Chunk::OP => Record::Chunk(Chunk::read(header, c)?),
+            ChunkInfo::OP => Record::ChunkInfo(ChunkInfo::read(header, c)?),
+            Connection::OP => Record::Connection(Connection::read(header, c)?),
+            MessageData::OP => Record::MessageData(MessageData::read(header, c)?),
+            op => {
+                c.next_chunk()?;
+                return Err(Error::UnknownRecord(op));
             }
+        };
+        if c.pos() != data_end {
+            return Err(Error::InvalidRecord);
         }
-
-        Ok(match op {
-            Some(IndexData::OP) => Record::IndexData(IndexData::read(header, c)?),
-            Some(Chunk::OP) => Record::Chunk(Chunk::read(header, c)?),
-            Some(ChunkInfo::OP) => Record::ChunkInfo(ChunkInfo::read(header, c)?),
-            Some(Connection::OP) => Record::Connection(Connection::read(header, c)?),
-            Some(MessageData::OP) => Record::MessageData(MessageData::read(header, c)?),
-            _ => return Err(Error::InvalidRecord),
-        })
+        Ok(record)
     }
 
-    /// Get string name of the stored recrod type.
-    pub fn get_type(&self) -> &'static str {
+    /// Get string name of the stored record type.
+    pub fn kind(&self) -> &'static str {
         match self {
             Record::Chunk(_) => "Chunk",
             Record::Connection(_) => "Connection",
@@ -53,3 +69,125 @@ impl<'a> Record<'a> {
         }
     }
 }
+
+impl<'a> From<ChunkRecord<'a>> for Record<'a> {
+    fn from(record: ChunkRecord<'a>) -> Self {
+        match record {
+            ChunkRecord::Chunk(v) => Record::Chunk(v),
+            ChunkRecord::IndexData(v) => Record::IndexData(v),
+        }
+    }
+}
+
+impl<'a> From<IndexRecord<'a>> for Record<'a> {
+    fn from(record: IndexRecord<'a>) -> Self {
+        match record {
+            IndexRecord::IndexData(v) => Record::IndexData(v),
+            IndexRecord::Connection(v) => Record::Connection(v),
+            IndexRecord::ChunkInfo(v) => Record::ChunkInfo(v),
+        }
+    }
+}
+
+impl<'a> From<MessageRecord<'a>> for Record<'a> {
+    fn from(record: MessageRecord<'a>) -> Self {
+        match record {
+            MessageRecord::MessageData(v) => Record::MessageData(v),
+            MessageRecord::Connection(v) => Record::Connection(v),
+        }
+    }
+}
+
+/// Check if record with unknown `op` should be skipped by record iterators
+/// and report skipped records using `log::warn!`.
+pub(crate) fn skip_unknown_record(op: u8, skip_unknown: bool) -> bool {
+    if op == LEGACY_MSG_DEF_OP {
+        log::warn!("skipping legacy message definition record");
+    } else if skip_unknown {
+        log::warn!("skipping record with unknown op: {:#04x}", op);
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Read record header and data without parsing them.
+///
+/// Returns `op` value of the record, its header and its data.
+pub(crate) fn next_raw_record<'a>(c: &mut Cursor<'a>) -> Result<(u8, &'a [u8], &'a [u8])> {
+    c.check_bag_start()?;
+    let header = c.next_chunk()?;
+    let op = read_op(header)?;
+    let data = c.next_chunk()?;
+    Ok((op, header, data))
+}
+
+/// Move cursor past the next record using only its length prefixes.
+pub(crate) fn skip_record(c: &mut Cursor<'_>) -> Result<()> {
+    c.check_bag_start()?;
+    c.next_chunk()?;
+    c.next_chunk()?;
+    Ok(())
+}
+
+/// Get `op` value of the record starting at `pos` after checking that
+/// lengths of its header and data fit into the cursor data.
+///
+/// Returns [`Error::UnknownRecord`] for `op` values which are not in `ops`
+/// and are not known, and `unexpected` error for known ones.
+pub(crate) fn check_record_at(
+    c: &Cursor<'_>,
+    pos: u64,
+    ops: &[u8],
+    unexpected: fn(&'static str) -> Error,
+) -> Result<()> {
+    let mut c = c.clone();
+    c.seek(pos)?;
+    let (op, _, _) = next_raw_record(&mut c)?;
+    if ops.contains(&op) {
+        return Ok(());
+    }
+    Err(match op_type(op) {
+        Some(t) => unexpected(t),
+        None => Error::UnknownRecord(op),
+    })
+}
+
+/// Read complete on-disk representation of the next record without parsing
+/// it beyond its `op` field.
+pub(crate) fn next_record_bytes<'a>(c: &mut Cursor<'a>) -> Result<(u8, &'a [u8])> {
+    let start = c.pos();
+    let (op, _, _) = next_raw_record(c)?;
+    let len = c.pos() - start;
+    c.seek(start)?;
+    Ok((op, c.next_bytes(len)?))
+}
+
+/// Read value of the `op` field from the record header.
+///
+/// The header must contain exactly one `op` field with a single byte value.
+pub(crate) fn read_op(header: &[u8]) -> Result<u8> {
+    let mut op = None;
+    for item in FieldIterator::new(header) {
+        let (name, val) = item?;
+        if name == "op" {
+            match (op, val) {
+                (None, [v]) => op = Some(*v),
+                _ => return Err(Error::InvalidRecord),
+            }
+        }
+    }
+    op.ok_or(Error::InvalidRecord)
+}
+
+/// Get string name of the record type with the given `op` value.
+pub(crate) fn op_type(op: u8) -> Option<&'static str> {
+    Some(match op {
+        Chunk::OP => "Chunk",
+        Connection::OP => "Connection",
+        MessageData::OP => "MessageData",
+        IndexData::OP => "IndexData",
+        ChunkInfo::OP => "ChunkInfo",
+        _ => return None,
+    })
+}
@@ -1,12 +1,18 @@
 use super::utils::{set_field_u32, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::{Connection, Error, HeaderGen, RecordGen, Result};
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
 use crate::cursor::Cursor;
-use crate::msg_iter::MessageRecordsIterator;
+use crate::msg_iter::{MessageRecordsIterator, ResolvedMessages};
+use crate::record::read_op;
+use crate::FieldIterator;
+use crate::UnknownFieldPolicy;
 
 /// Compression options for `Chunk` data.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum Compression {
     /// Bzip2 compression.
@@ -15,14 +21,83 @@ pub enum Compression {
     Lz4,
     /// No compression.
     None,
+    /// Compression not supported by the crate.
+    ///
+    /// Such chunks can be read only if a decompressor was registered with
+    /// [`OpenOptions::register_decompressor`][crate::OpenOptions::register_decompressor].
+    /// Name of the compression is stored in the `compression` header field,
+    /// see [`Chunk::header_fields`].
+    Custom,
+}
+
+/// Function used for decompression of [`Compression::Custom`] chunks.
+///
+/// Receives compressed data and the decompressed size declared in the chunk
+/// header.
+pub type Decompressor = Arc<dyn Fn(&[u8], Option<u32>) -> Result<Vec<u8>> + Send + Sync>;
+
+/// Registry of custom decompressors keyed by compression name.
+#[derive(Clone, Default)]
+pub(crate) struct Decompressors(HashMap<String, Decompressor>);
+
+impl Decompressors {
+    pub fn insert(&mut self, name: &str, f: Decompressor) {
+        self.0.insert(name.to_string(), f);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Decompressor> {
+        self.0.get(name)
+    }
+}
+
+impl fmt::Debug for Decompressors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
 }
 
 impl Compression {
-    fn decompress(self, data: &[u8], decompressed_size: Option<u32>) -> Result<Cow<'_, [u8]>> {
+    /// Decompress chunk data which was read without the help of this crate.
+    ///
+    /// `decompressed_size` is the value of the `size` field from the chunk
+    /// header. If provided, it is used for preallocation and the decompressed
+    /// data length is checked against it like when chunks are read from a
+    /// bag. [`Compression::Custom`] data results in
+    /// [`Error::UnsupportedCompression`] with an empty name, as decompressors
+    /// registered with [`OpenOptions`][crate::OpenOptions] are not available
+    /// here.
+    pub fn decompress(&self, data: &[u8], decompressed_size: Option<u32>) -> Result<Vec<u8>> {
+        let data = self.decompress_chunk("", data, decompressed_size, &Cursor::new(&[]))?;
+        match decompressed_size {
+            Some(size) if data.len() != size as usize => Err(Error::DecompressionSizeMismatch {
+                expected: size,
+                actual: data.len(),
+                compression: *self,
+            }),
+            _ => Ok(data.into_owned()),
+        }
+    }
+
+    /// Decompress `data`, using decompressor registered in `c` under `name`
+    /// for custom compressions.
+    fn decompress_chunk<'a>(
+        &self,
+        name: &str,
+        data: &'a [u8],
+        decompressed_size: Option<u32>,
+        c: &Cursor<'_>,
+    ) -> Result<Cow<'a, [u8]>> {
+        let _span = span!(
+            DEBUG,
+            "decompress",
+            compression = ?self,
+            compressed_len = data.len(),
+            decompressed_len = ?decompressed_size,
+        );
         Ok(match self {
             Compression::Bzip2 => {
-                let mut decompressed = Vec::new();
-                decompressed.reserve(decompressed_size.map(|s| s as usize).unwrap_or(data.len()));
+                let mut decompressed =
+                    Vec::with_capacity(decompressed_size.map(|s| s as usize).unwrap_or(data.len()));
                 let mut decompressor = bzip2::Decompress::new(false);
                 decompressor
                     .decompress_vec(data, &mut decompressed)
@@ -32,14 +107,20 @@ impl Compression {
             Compression::Lz4 => {
                 let mut decoder = lz4::Decoder::new(data)
                     .map_err(|e| Error::Lz4DecompressionError(e.to_string()))?;
-                let mut decompressed = Vec::new();
-                decompressed.reserve(decompressed_size.map(|s| s as usize).unwrap_or(data.len()));
+                let mut decompressed =
+                    Vec::with_capacity(decompressed_size.map(|s| s as usize).unwrap_or(data.len()));
                 std::io::copy(&mut decoder, &mut decompressed).map_err(|_| {
                     Error::Lz4DecompressionError("Error while decoding".to_string())
                 })?;
                 Cow::from(decompressed)
             }
             Compression::None => Cow::from(data),
+            Compression::Custom => {
+                let f = c
+                    .decompressor(name)
+                    .ok_or_else(|| Error::UnsupportedCompression(name.to_string()))?;
+                Cow::from(f(data, decompressed_size)?)
+            }
         })
     }
 }
@@ -50,41 +131,153 @@ impl Compression {
 pub struct Chunk<'a> {
     /// Compression type for the data
     pub compression: Compression,
+    /// Size of the (possibly compressed) data stored in the record
+    pub compressed_len: u32,
+    /// Size of the data after decompression
+    pub uncompressed_len: u32,
     /// Decompressed messages data and connection records
     data: Cow<'a, [u8]>,
+    /// Raw record header
+    header: &'a [u8],
+    /// Handling of unknown header fields in records stored in the chunk
+    unknown_fields: UnknownFieldPolicy,
 }
 
 impl<'a> Chunk<'a> {
+    /// Get iterator over all fields of the record header, including fields
+    /// which are not exposed by this type.
+    pub fn header_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.header)
+    }
+
+    /// Get decompressed chunk data.
+    ///
+    /// The data contains `Connection` and `MessageData` records which are
+    /// parsed by [`Chunk::messages`].
+    pub fn decompressed_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
     /// Get iterator over only messages
     pub fn messages(&self) -> MessageRecordsIterator<'_> {
-        MessageRecordsIterator::new(&self.data)
+        let mut iter = MessageRecordsIterator::new(&self.data);
+        iter.cursor = iter.cursor.with_unknown_field_policy(self.unknown_fields);
+        iter
+    }
+
+    /// Get iterator over `Connection` records stored in the chunk.
+    ///
+    /// Other records are skipped using their length prefixes without being
+    /// parsed, which is faster than filtering [`Chunk::messages`] output.
+    /// Iteration stops after the first error.
+    pub fn connections(&self) -> impl Iterator<Item = Result<Connection<'_>>> + '_ {
+        let mut cursor = self.cursor();
+        std::iter::from_fn(move || {
+            while cursor.left() != 0 {
+                match next_connection(&mut cursor) {
+                    Ok(None) => continue,
+                    Ok(Some(conn)) => return Some(Ok(conn)),
+                    Err(err) => {
+                        cursor.finish();
+                        return Some(Err(err));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Get iterator over messages which continues after corrupted records.
+    ///
+    /// See [`MessageRecordsIterator::lenient`] for details.
+    pub fn lenient_messages(&self) -> MessageRecordsIterator<'_> {
+        self.messages().lenient(true)
+    }
+
+    /// Get iterator over messages together with their connections.
+    ///
+    /// See [`MessageRecordsIterator::resolve_connections`] for details.
+    pub fn messages_resolved(&self) -> ResolvedMessages<'_> {
+        self.messages().resolve_connections()
+    }
+
+    fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(&self.data).with_unknown_field_policy(self.unknown_fields)
+    }
+
+    /// Convert into owned decompressed data.
+    pub(crate) fn into_data(self) -> Vec<u8> {
+        self.data.into_owned()
+    }
+}
+
+/// Get value of the `compression` field from the raw chunk record header.
+fn compression_name(header: &[u8]) -> Result<&str> {
+    for field in FieldIterator::new(header) {
+        if let ("compression", val) = field? {
+            return std::str::from_utf8(val).map_err(|_| Error::InvalidHeader);
+        }
     }
+    Err(Error::InvalidHeader)
+}
+
+/// Read the next record if it is a `Connection` or skip it otherwise.
+fn next_connection<'a>(c: &mut Cursor<'a>) -> Result<Option<Connection<'a>>> {
+    let header = c.next_chunk()?;
+    if read_op(header)? != Connection::OP {
+        c.next_chunk()?;
+        return Ok(None);
+    }
+    Connection::read(header, c).map(Some)
 }
 
 #[derive(Debug, Clone, Default)]
 pub(crate) struct ChunkHeader {
-    compression: Option<Compression>,
-    size: Option<u32>,
+    pub compression: Option<Compression>,
+    pub size: Option<u32>,
 }
 
 impl<'a> RecordGen<'a> for Chunk<'a> {
     type Header = ChunkHeader;
 
-    fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
+    fn read_data(c: &mut Cursor<'a>, header: Self::Header, raw: &'a [u8]) -> Result<Self> {
         let compression = header.compression.ok_or(Error::InvalidHeader)?;
         let size = header.size.ok_or(Error::InvalidHeader)?;
-        let data = compression.decompress(c.next_chunk()?, header.size)?;
+        if size > c.limit() {
+            return Err(Error::RecordTooLarge {
+                declared: size,
+                limit: c.limit(),
+            });
+        }
+        let compressed = c.next_chunk()?;
+        let compressed_len = compressed.len() as u32;
+        let name = match compression {
+            Compression::Custom => compression_name(raw)?,
+            _ => "",
+        };
+        let data = compression.decompress_chunk(name, compressed, header.size, c)?;
         if data.len() != size as usize {
-            return Err(Error::InvalidRecord);
+            return Err(Error::DecompressionSizeMismatch {
+                expected: size,
+                actual: data.len(),
+                compression,
+            });
         }
-        Ok(Self { compression, data })
+        Ok(Self {
+            compression,
+            compressed_len,
+            uncompressed_len: size,
+            data,
+            header: raw,
+            unknown_fields: c.unknown_field_policy(),
+        })
     }
 }
 
 impl<'a> HeaderGen<'a> for ChunkHeader {
     const OP: u8 = 0x05;
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
         match name {
             "compression" => {
                 if self.compression.is_some() {
@@ -94,11 +287,14 @@ impl<'a> HeaderGen<'a> for ChunkHeader {
                     b"none" => Compression::None,
                     b"bz2" => Compression::Bzip2,
                     b"lz4" => Compression::Lz4,
-                    _ => return Err(Error::InvalidHeader),
+                    name => {
+                        std::str::from_utf8(name).map_err(|_| Error::InvalidHeader)?;
+                        Compression::Custom
+                    }
                 });
             }
             "size" => set_field_u32(&mut self.size, val)?,
-            _ => unknown_field(name, val),
+            _ => unknown_field(name, val, policy)?,
         }
         Ok(())
     }
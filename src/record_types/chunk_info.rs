@@ -1,7 +1,10 @@
-use super::utils::{set_field_time, set_field_u32, set_field_u64, unknown_field};
+use super::utils::{set_field_time, set_field_u32, set_field_u64, unknown_field, DisplayTime};
 use super::{Error, HeaderGen, RecordGen, Result};
 
 use crate::cursor::Cursor;
+use crate::FieldIterator;
+use crate::UnknownFieldPolicy;
+use std::fmt;
 
 /// High-level index of `Chunk` records.
 #[derive(Debug, Clone)]
@@ -16,17 +19,38 @@ pub struct ChunkInfo<'a> {
     pub end_time: u64,
     /// Index entries data
     data: &'a [u8],
+    /// Raw record header
+    header: &'a [u8],
 }
 
 impl<'a> ChunkInfo<'a> {
+    /// Get iterator over all fields of the record header, including fields
+    /// which are not exposed by this type.
+    pub fn header_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.header)
+    }
+
     /// Get entries iterator.
-    pub fn entries(&'a self) -> ChunkInfoEntriesIterator<'a> {
+    pub fn entries(&self) -> ChunkInfoEntriesIterator<'a> {
         ChunkInfoEntriesIterator {
             cursor: Cursor::new(self.data),
         }
     }
 }
 
+impl<'a> fmt::Display for ChunkInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chunk_pos={} [{}, {}] entries={}",
+            self.chunk_pos,
+            DisplayTime(self.start_time),
+            DisplayTime(self.end_time),
+            self.data.len() / 8
+        )
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct ChunkInfoHeader {
     pub ver: Option<u32>,
@@ -39,7 +63,7 @@ pub(crate) struct ChunkInfoHeader {
 impl<'a> RecordGen<'a> for ChunkInfo<'a> {
     type Header = ChunkInfoHeader;
 
-    fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
+    fn read_data(c: &mut Cursor<'a>, header: Self::Header, raw: &'a [u8]) -> Result<Self> {
         let ver = header.ver.ok_or(Error::InvalidHeader)?;
         let chunk_pos = header.chunk_pos.ok_or(Error::InvalidHeader)?;
         let start_time = header.start_time.ok_or(Error::InvalidHeader)?;
@@ -60,6 +84,7 @@ impl<'a> RecordGen<'a> for ChunkInfo<'a> {
             start_time,
             end_time,
             data,
+            header: raw,
         })
     }
 }
@@ -67,14 +92,14 @@ impl<'a> RecordGen<'a> for ChunkInfo<'a> {
 impl<'a> HeaderGen<'a> for ChunkInfoHeader {
     const OP: u8 = 0x06;
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
         match name {
             "ver" => set_field_u32(&mut self.ver, val)?,
             "chunk_pos" => set_field_u64(&mut self.chunk_pos, val)?,
             "start_time" => set_field_time(&mut self.start_time, val)?,
             "end_time" => set_field_time(&mut self.end_time, val)?,
             "count" => set_field_u32(&mut self.count, val)?,
-            _ => unknown_field(name, val),
+            _ => unknown_field(name, val, policy)?,
         }
         Ok(())
     }
@@ -1,16 +1,20 @@
 use super::utils::{check_op, read_record, unknown_field};
 use super::utils::{set_field_str, set_field_u32};
 use super::{Error, HeaderGen, RecordGen, Result};
-use log::warn;
+use std::fmt;
 
 use crate::cursor::Cursor;
 use crate::field_iter::FieldIterator;
+use crate::UnknownFieldPolicy;
 
 /// Connection record which contains message type for ROS topic.
 ///
 /// Two topic fields exist `storage_topic` and `topic`. This is because messages
 /// can be written to the bag file on a topic different from where they were
-/// originally published.
+/// originally published. `topic` is the topic on which messages were
+/// published, while `storage_topic` is the topic under which they are stored
+/// in the bag file (and under which tools like `rosbag play` replay them).
+/// Use [`Connection::is_remapped`] to check if the two differ.
 #[derive(Debug, Clone)]
 pub struct Connection<'a> {
     /// Unique connection ID
@@ -31,6 +35,44 @@ pub struct Connection<'a> {
     /// Is publisher in the latching mode? (i.e. sends the last value published
     /// to new subscribers)
     pub latching: bool,
+    /// Raw record header
+    header: &'a [u8],
+    /// Raw connection header stored in the record data
+    data: &'a [u8],
+}
+
+impl<'a> Connection<'a> {
+    /// Get iterator over all fields of the record header, including fields
+    /// which are not exposed by this type.
+    pub fn header_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.header)
+    }
+
+    /// Get iterator over all fields of the connection header stored in the
+    /// record data, including fields which are not exposed by this type.
+    pub fn data_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.data)
+    }
+
+    /// Check if messages are stored under a topic different from the one
+    /// they were published on.
+    pub fn is_remapped(&self) -> bool {
+        self.storage_topic != self.topic
+    }
+
+    /// Append on-disk representation of the record to `buf`.
+    pub(crate) fn write_record(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.header.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.header);
+        buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.data);
+    }
+}
+
+impl<'a> fmt::Display for Connection<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}, id={})", self.storage_topic, self.tp, self.id)
+    }
 }
 
 #[derive(Default, Debug)]
@@ -42,7 +84,7 @@ pub(crate) struct ConnectionHeader<'a> {
 impl<'a> RecordGen<'a> for Connection<'a> {
     type Header = ConnectionHeader<'a>;
 
-    fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
+    fn read_data(c: &mut Cursor<'a>, header: Self::Header, raw: &'a [u8]) -> Result<Self> {
         let id = header.id.ok_or(Error::InvalidHeader)?;
         let storage_topic = header.storage_topic.ok_or(Error::InvalidHeader)?;
 
@@ -61,11 +103,19 @@ impl<'a> RecordGen<'a> for Connection<'a> {
                 "topic" => set_field_str(&mut topic, val)?,
                 "type" => set_field_str(&mut tp, val)?,
                 "md5sum" => {
-                    if md5sum.is_some() || val.len() != 32 {
+                    if md5sum.is_some() {
                         return Err(Error::InvalidRecord);
                     }
+                    let invalid_md5 = |reason| Error::InvalidMd5 {
+                        topic: Some(storage_topic.to_string()),
+                        reason,
+                    };
+                    if val.len() != 32 {
+                        return Err(invalid_md5("expected 32 hex characters"));
+                    }
                     let mut res = [0u8; 16];
-                    base16ct::lower::decode(val, &mut res).map_err(|_| Error::InvalidRecord)?;
+                    base16ct::lower::decode(val, &mut res)
+                        .map_err(|_| invalid_md5("invalid lowercase hex encoding"))?;
                     md5sum = Some(res);
                 }
                 "message_definition" => set_field_str(&mut message_definition, val)?,
@@ -77,7 +127,7 @@ impl<'a> RecordGen<'a> for Connection<'a> {
                         _ => return Err(Error::InvalidRecord),
                     }
                 }
-                _ => warn!("Unknown field in the connection header: {}", name),
+                _ => unknown_field(name, val, c.unknown_field_policy())?,
             }
         }
 
@@ -95,6 +145,8 @@ impl<'a> RecordGen<'a> for Connection<'a> {
             message_definition,
             caller_id,
             latching,
+            header: raw,
+            data: buf,
         })
     }
 }
@@ -102,7 +154,7 @@ impl<'a> RecordGen<'a> for Connection<'a> {
 impl<'a> HeaderGen<'a> for ConnectionHeader<'a> {
     const OP: u8 = 0x07;
 
-    fn read_header(mut header: &'a [u8]) -> Result<Self> {
+    fn read_header(mut header: &'a [u8], policy: UnknownFieldPolicy) -> Result<Self> {
         let mut rec = Self::default();
         while !header.is_empty() {
             let (name, val, new_header) = read_record(header)?;
@@ -110,16 +162,16 @@ impl<'a> HeaderGen<'a> for ConnectionHeader<'a> {
             match name {
                 "op" => check_op(val, Self::OP)?,
                 "topic" => set_field_str(&mut rec.storage_topic, val)?,
-                _ => rec.process_field(name, val)?,
+                _ => rec.process_field(name, val, policy)?,
             }
         }
         Ok(rec)
     }
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
         match name {
             "conn" => set_field_u32(&mut self.id, val)?,
-            _ => unknown_field(name, val),
+            _ => unknown_field(name, val, policy)?,
         }
         Ok(())
     }
@@ -2,6 +2,9 @@ use super::utils::{set_field_u32, unknown_field};
 use super::{Error, HeaderGen, RecordGen, Result};
 
 use crate::cursor::Cursor;
+use crate::FieldIterator;
+use crate::UnknownFieldPolicy;
+use std::fmt;
 
 /// Index record which describes messages offset for `Connection` with
 /// `conn_id` ID in the preceding `Chunk`.
@@ -13,17 +16,31 @@ pub struct IndexData<'a> {
     pub conn_id: u32,
     /// Occurrences of timestamps, chunk record offsets and message offsets
     data: &'a [u8],
+    /// Raw record header
+    header: &'a [u8],
 }
 
 impl<'a> IndexData<'a> {
+    /// Get iterator over all fields of the record header, including fields
+    /// which are not exposed by this type.
+    pub fn header_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.header)
+    }
+
     /// Get entries iterator.
-    pub fn entries(&'a self) -> IndexDataEntriesIterator<'a> {
+    pub fn entries(&self) -> IndexDataEntriesIterator<'a> {
         IndexDataEntriesIterator {
             cursor: Cursor::new(self.data),
         }
     }
 }
 
+impl<'a> fmt::Display for IndexData<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "conn={} entries={}", self.conn_id, self.data.len() / 12)
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct IndexDataHeader {
     pub ver: Option<u32>,
@@ -34,7 +51,7 @@ pub(crate) struct IndexDataHeader {
 impl<'a> RecordGen<'a> for IndexData<'a> {
     type Header = IndexDataHeader;
 
-    fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
+    fn read_data(c: &mut Cursor<'a>, header: Self::Header, raw: &'a [u8]) -> Result<Self> {
         let ver = header.ver.ok_or(Error::InvalidHeader)?;
         let conn_id = header.conn_id.ok_or(Error::InvalidHeader)?;
         let count = header.count.ok_or(Error::InvalidHeader)?;
@@ -47,19 +64,24 @@ impl<'a> RecordGen<'a> for IndexData<'a> {
             return Err(Error::InvalidRecord);
         }
         let data = c.next_bytes(n as u64)?;
-        Ok(Self { ver, conn_id, data })
+        Ok(Self {
+            ver,
+            conn_id,
+            data,
+            header: raw,
+        })
     }
 }
 
 impl<'a> HeaderGen<'a> for IndexDataHeader {
     const OP: u8 = 0x04;
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
         match name {
             "ver" => set_field_u32(&mut self.ver, val)?,
             "conn" => set_field_u32(&mut self.conn_id, val)?,
             "count" => set_field_u32(&mut self.count, val)?,
-            _ => unknown_field(name, val),
+            _ => unknown_field(name, val, policy)?,
         }
         Ok(())
     }
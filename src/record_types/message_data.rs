@@ -1,6 +1,11 @@
-use super::utils::{set_field_time, set_field_u32, unknown_field};
-use super::{Error, HeaderGen, RecordGen, Result};
+use super::utils::{set_field_time, set_field_u32, unknown_field, DisplayTime};
+use super::{Connection, Error, HeaderGen, RecordGen, Result};
 use crate::cursor::Cursor;
+use crate::FieldIterator;
+use crate::UnknownFieldPolicy;
+use std::borrow::Cow;
+use std::fmt;
+use std::mem::size_of;
 
 /// Message data for a `Connection` with `conn_id` ID.
 #[derive(Debug, Clone)]
@@ -11,8 +16,164 @@ pub struct MessageData<'a> {
     pub time: u64,
     /// Serialized message data in the ROS serialization format
     pub data: &'a [u8],
+    /// Raw record header
+    header: &'a [u8],
 }
 
+impl<'a> fmt::Display for MessageData<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conn={} t={} len={}",
+            self.conn_id,
+            DisplayTime(self.time),
+            self.data.len()
+        )
+    }
+}
+
+/// Decoded `std_msgs/Header` stored at the beginning of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StdHeader<'a> {
+    /// Sequence ID
+    pub seq: u32,
+    /// Header timestamp in nanoseconds of UNIX epoch
+    pub stamp: u64,
+    /// Frame this data is associated with
+    pub frame_id: &'a str,
+}
+
+impl<'a> MessageData<'a> {
+    /// Get iterator over all fields of the record header, including fields
+    /// which are not exposed by this type.
+    pub fn header_fields(&self) -> FieldIterator<'a> {
+        FieldIterator::new(self.header)
+    }
+
+    /// Get size of the record in its on-disk representation.
+    ///
+    /// Includes the record header, data and their length prefixes. Note that
+    /// messages are usually stored in compressed chunks, so the value
+    /// reflects size in decompressed chunk data.
+    pub fn record_len(&self) -> u64 {
+        8 + self.header.len() as u64 + self.data.len() as u64
+    }
+
+    /// Serialize the record into its on-disk representation.
+    ///
+    /// The emitted header contains only `op`, `conn` and `time` fields, so
+    /// other fields present in the original header are not preserved.
+    pub fn to_record_bytes(&self) -> Vec<u8> {
+        let secs = (self.time / 1_000_000_000) as u32;
+        let nsecs = (self.time % 1_000_000_000) as u32;
+        let mut time = [0u8; 8];
+        time[..4].copy_from_slice(&secs.to_le_bytes());
+        time[4..].copy_from_slice(&nsecs.to_le_bytes());
+        let fields: [(&str, &[u8]); 3] = [
+            ("op", &[MessageDataHeader::OP]),
+            ("conn", &self.conn_id.to_le_bytes()),
+            ("time", &time),
+        ];
+
+        let header_len: usize = fields.iter().map(|(n, v)| 5 + n.len() + v.len()).sum();
+        let mut buf = Vec::with_capacity(8 + header_len + self.data.len());
+        buf.extend_from_slice(&(header_len as u32).to_le_bytes());
+        for (name, val) in fields {
+            let len = name.len() + 1 + val.len();
+            buf.extend_from_slice(&(len as u32).to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(b'=');
+            buf.extend_from_slice(val);
+        }
+        buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.data);
+        buf
+    }
+
+    /// Decode leading `std_msgs/Header` of the message.
+    ///
+    /// `conn` must be the connection on which message arrived. Returns `None`
+    /// if the first field in the connection message definition is not a
+    /// `Header`. Only the header fields get decoded, the rest of the
+    /// message is left untouched.
+    pub fn peek_header(&self, conn: &Connection<'_>) -> Result<Option<StdHeader<'a>>> {
+        if !starts_with_header(conn.message_definition) {
+            return Ok(None);
+        }
+        let mut c = Cursor::new(self.data);
+        let seq = c.next_u32()?;
+        let stamp = c.next_time()?;
+        let frame_id = c.next_chunk()?;
+        let frame_id = std::str::from_utf8(frame_id).map_err(|_| Error::InvalidRecord)?;
+        Ok(Some(StdHeader {
+            seq,
+            stamp,
+            frame_id,
+        }))
+    }
+}
+
+/// Check if the first field in message definition has `Header` type.
+///
+/// Comments and constant declarations (e.g. `uint8 MODE=1`) are skipped,
+/// since they do not occupy space in the serialized message.
+fn starts_with_header(definition: &str) -> bool {
+    let field = definition
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .find(|line| !line.is_empty() && !line.contains('='));
+    match field.and_then(|f| f.split_whitespace().next()) {
+        Some(tp) => tp == "Header" || tp == "std_msgs/Header",
+        None => false,
+    }
+}
+
+macro_rules! impl_as_slice {
+    ($($name:ident: $t:ty,)*) => {
+        impl<'a> MessageData<'a> {
+            $(
+                #[doc = concat!(
+                    "Interpret the whole payload as a little-endian `[", stringify!($t), "]` array.\n\n",
+                    "Returns a borrowed slice without copying if payload is properly aligned ",
+                    "and the target is little-endian, otherwise values are copied into ",
+                    "a new vector. Returns `None` if payload length is not a multiple ",
+                    "of the element size.",
+                )]
+                pub fn $name(&self) -> Option<Cow<'a, [$t]>> {
+                    const N: usize = size_of::<$t>();
+                    if self.data.len() % N != 0 {
+                        return None;
+                    }
+                    if cfg!(target_endian = "little") {
+                        // SAFETY: any bit pattern is a valid value of the target type
+                        let (prefix, vals, suffix) = unsafe { self.data.align_to::<$t>() };
+                        if prefix.is_empty() && suffix.is_empty() {
+                            return Some(Cow::Borrowed(vals));
+                        }
+                    }
+                    let vals = self
+                        .data
+                        .chunks_exact(N)
+                        .map(|b| <$t>::from_le_bytes(b.try_into().expect("chunk has correct size")))
+                        .collect();
+                    Some(Cow::Owned(vals))
+                }
+            )*
+        }
+    };
+}
+
+impl_as_slice!(
+    as_i16_slice: i16,
+    as_u16_slice: u16,
+    as_i32_slice: i32,
+    as_u32_slice: u32,
+    as_i64_slice: i64,
+    as_u64_slice: u64,
+    as_f32_slice: f32,
+    as_f64_slice: f64,
+);
+
 #[derive(Default, Debug)]
 pub(crate) struct MessageDataHeader {
     pub conn_id: Option<u32>,
@@ -22,7 +183,7 @@ pub(crate) struct MessageDataHeader {
 impl<'a> RecordGen<'a> for MessageData<'a> {
     type Header = MessageDataHeader;
 
-    fn read_data(c: &mut Cursor<'a>, header: Self::Header) -> Result<Self> {
+    fn read_data(c: &mut Cursor<'a>, header: Self::Header, raw: &'a [u8]) -> Result<Self> {
         let conn_id = header.conn_id.ok_or(Error::InvalidHeader)?;
         let time = header.time.ok_or(Error::InvalidHeader)?;
         let data = c.next_chunk()?;
@@ -30,6 +191,7 @@ impl<'a> RecordGen<'a> for MessageData<'a> {
             conn_id,
             time,
             data,
+            header: raw,
         })
     }
 }
@@ -37,11 +199,11 @@ impl<'a> RecordGen<'a> for MessageData<'a> {
 impl<'a> HeaderGen<'a> for MessageDataHeader {
     const OP: u8 = 0x02;
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()> {
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
         match name {
             "conn" => set_field_u32(&mut self.conn_id, val)?,
             "time" => set_field_time(&mut self.time, val)?,
-            _ => unknown_field(name, val),
+            _ => unknown_field(name, val, policy)?,
         }
         Ok(())
     }
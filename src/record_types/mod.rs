@@ -2,11 +2,13 @@
 use super::{Error, Result};
 
 use crate::cursor::Cursor;
+use crate::record::Record;
+use crate::UnknownFieldPolicy;
 
-mod chunk;
-pub use self::chunk::{Chunk, Compression};
+pub(crate) mod chunk;
+pub use self::chunk::{Chunk, Compression, Decompressor};
 pub(crate) mod message_data;
-pub use self::message_data::MessageData;
+pub use self::message_data::{MessageData, StdHeader};
 pub(crate) mod connection;
 pub use self::connection::Connection;
 mod index_data;
@@ -14,27 +16,46 @@ pub use self::index_data::{IndexData, IndexDataEntriesIterator, IndexDataEntry};
 mod chunk_info;
 pub use self::chunk_info::{ChunkInfo, ChunkInfoEntriesIterator, ChunkInfoEntry};
 
-pub(crate) mod utils;
+pub mod utils;
 use self::utils::{check_op, read_record};
 
+/// Decode a single record stored in `buf`.
+///
+/// The buffer must contain exactly one record in its on-disk
+/// representation, i.e. length-prefixed header followed by length-prefixed
+/// data.
+pub fn decode_record(buf: &[u8]) -> Result<Record<'_>> {
+    let mut c = Cursor::new(buf);
+    let record = Record::next_record(&mut c)?;
+    if c.left() != 0 {
+        return Err(Error::InvalidRecord);
+    }
+    Ok(record)
+}
+
 pub(crate) trait HeaderGen<'a>: Sized + Default {
     const OP: u8;
 
-    fn read_header(mut header: &'a [u8]) -> Result<Self> {
+    fn read_header(mut header: &'a [u8], policy: UnknownFieldPolicy) -> Result<Self> {
         let mut rec = Self::default();
+        let mut op = false;
         while !header.is_empty() {
             let (name, val, new_header) = read_record(header)?;
             header = new_header;
             if name == "op" {
+                if op {
+                    return Err(Error::InvalidRecord);
+                }
                 check_op(val, Self::OP)?;
+                op = true;
             } else {
-                rec.process_field(name, val)?;
+                rec.process_field(name, val, policy)?;
             }
         }
         Ok(rec)
     }
 
-    fn process_field(&mut self, name: &str, val: &[u8]) -> Result<()>;
+    fn process_field(&mut self, name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()>;
 }
 
 pub(crate) trait RecordGen<'a>: Sized {
@@ -44,9 +65,10 @@ pub(crate) trait RecordGen<'a>: Sized {
     type Header: HeaderGen<'a>;
 
     fn read(header: &'a [u8], c: &mut Cursor<'a>) -> Result<Self> {
-        let header = Self::Header::read_header(header)?;
-        Self::read_data(c, header)
+        let raw = header;
+        let header = Self::Header::read_header(header, c.unknown_field_policy())?;
+        Self::read_data(c, header, raw)
     }
 
-    fn read_data(c: &mut Cursor<'a>, h: Self::Header) -> Result<Self>;
+    fn read_data(c: &mut Cursor<'a>, h: Self::Header, raw: &'a [u8]) -> Result<Self>;
 }
@@ -1,6 +1,8 @@
+//! Helpers for record parsing and formatting of bag timestamps.
 use super::{Error, Result};
+use crate::UnknownFieldPolicy;
 use byteorder::{ByteOrder, LE};
-use std::str;
+use std::{fmt, str};
 
 pub(crate) fn read_record(mut header: &[u8]) -> Result<(&str, &[u8], &[u8])> {
     if header.len() < 4 {
@@ -36,8 +38,13 @@ pub(crate) fn read_record(mut header: &[u8]) -> Result<(&str, &[u8], &[u8])> {
     Ok((name, val, header))
 }
 
-pub(crate) fn unknown_field(name: &str, val: &[u8]) {
-    log::warn!("Unknown header field: {}={:?}", name, val);
+pub(crate) fn unknown_field(name: &str, val: &[u8], policy: UnknownFieldPolicy) -> Result<()> {
+    match policy {
+        UnknownFieldPolicy::Ignore => {}
+        UnknownFieldPolicy::Warn => log::warn!("Unknown header field: {}={:?}", name, val),
+        UnknownFieldPolicy::Error => return Err(Error::UnknownField(name.to_string())),
+    }
+    Ok(())
 }
 
 pub(crate) fn check_op(val: &[u8], op: u8) -> Result<()> {
@@ -83,3 +90,69 @@ pub(crate) fn set_field_time(field: &mut Option<u64>, val: &[u8]) -> Result<()>
     *field = Some(1_000_000_000 * s + ns);
     Ok(())
 }
+
+/// Wrapper which displays nanosecond timestamps as `<secs>.<nsecs>`.
+pub(crate) struct DisplayTime(pub u64);
+
+impl fmt::Display for DisplayTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:09}",
+            self.0 / 1_000_000_000,
+            self.0 % 1_000_000_000
+        )
+    }
+}
+
+/// Format nanoseconds since UNIX epoch as an RFC 3339 UTC timestamp, e.g.
+/// `2021-03-04T05:06:07.123456789Z`.
+pub fn format_time(ns: u64) -> String {
+    let secs = ns / 1_000_000_000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // civil-from-days conversion for the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        ns % 1_000_000_000,
+    )
+}
+
+/// Format duration in nanoseconds as a compact string, e.g. `1h23m4.5s`.
+///
+/// Hours and minutes are omitted if they are zero and trailing zeros of
+/// the fractional seconds are removed.
+pub fn format_duration(ns: u64) -> String {
+    let secs = ns / 1_000_000_000;
+    let (hours, mins) = (secs / 3600, secs / 60 % 60);
+    let mut res = String::new();
+    if hours != 0 {
+        res += &format!("{}h", hours);
+    }
+    if hours != 0 || mins != 0 {
+        res += &format!("{}m", mins);
+    }
+    res += &(secs % 60).to_string();
+    let frac = format!("{:09}", ns % 1_000_000_000);
+    let frac = frac.trim_end_matches('0');
+    if !frac.is_empty() {
+        res.push('.');
+        res += frac;
+    }
+    res.push('s');
+    res
+}
@@ -0,0 +1,311 @@
+//! Summary statistics computed from bag records.
+use crate::record::next_raw_record;
+use crate::record_types::chunk::ChunkHeader;
+use crate::record_types::{Chunk, ChunkInfo, Compression, HeaderGen, RecordGen};
+use crate::{ChunkRecord, Error, IndexRecord, MessageRecord, Result, RosBag};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Maximum number of buckets returned by [`RosBag::topic_rate_series`].
+const MAX_RATE_BUCKETS: u64 = 1 << 20;
+
+/// Sizes of chunks which use the same compression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Number of chunks
+    pub chunks: u32,
+    /// Total size of chunk data as stored in the file
+    pub compressed_bytes: u64,
+    /// Total size of chunk data after decompression
+    pub uncompressed_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Ratio of uncompressed to compressed data size.
+    ///
+    /// Returns 1 if there is no data.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 1.0;
+        }
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Connection IDs declared in the index section and referenced by chunks.
+///
+/// Returned by [`RosBag::connection_usage`]. All lists are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionUsage {
+    /// IDs of `Connection` records in the index section
+    pub declared: Vec<u32>,
+    /// IDs referenced by `ChunkInfo` entries
+    pub observed: Vec<u32>,
+    /// Declared IDs which are not referenced by any chunk
+    pub unused: Vec<u32>,
+    /// Referenced IDs without a declared connection
+    pub orphaned: Vec<u32>,
+}
+
+/// Results of [`RosBag::benchmark_scan`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Number of decoded messages
+    pub messages: u64,
+    /// Total size of chunk data after decompression
+    pub decompressed_bytes: u64,
+    /// Wall time spent on the scan
+    pub elapsed: Duration,
+}
+
+impl ScanStats {
+    /// Number of decoded messages per second.
+    ///
+    /// Returns 0 if no time has elapsed.
+    pub fn messages_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.messages as f64 / secs
+    }
+}
+
+impl RosBag {
+    /// Get chunk sizes grouped by compression type.
+    ///
+    /// Sizes are read from chunk record headers, chunk data does not get
+    /// decompressed. Chunks with compressions not supported by the crate are
+    /// counted together under [`Compression::Custom`].
+    pub fn compression_stats(&self) -> Result<HashMap<Compression, CompressionStats>> {
+        let mut res: HashMap<Compression, CompressionStats> = HashMap::new();
+        let mut cursor = self.cursor(self.start_pos, self.index_pos);
+        while cursor.left() != 0 {
+            let (op, header, data) = next_raw_record(&mut cursor)?;
+            if op != Chunk::OP {
+                continue;
+            }
+            let header = ChunkHeader::read_header(header, cursor.unknown_field_policy())?;
+            let compression = header.compression.ok_or(Error::InvalidHeader)?;
+            let size = header.size.ok_or(Error::InvalidHeader)?;
+            let stats = res.entry(compression).or_default();
+            stats.chunks += 1;
+            stats.compressed_bytes += data.len() as u64;
+            stats.uncompressed_bytes += size as u64;
+        }
+        Ok(res)
+    }
+
+    /// Get ratio of uncompressed to compressed size of all chunks in the bag.
+    ///
+    /// See [`RosBag::compression_stats`] for a breakdown by compression type.
+    pub fn compression_ratio(&self) -> Result<f64> {
+        let total =
+            self.compression_stats()?
+                .values()
+                .fold(CompressionStats::default(), |acc, s| CompressionStats {
+                    chunks: acc.chunks + s.chunks,
+                    compressed_bytes: acc.compressed_bytes + s.compressed_bytes,
+                    uncompressed_bytes: acc.uncompressed_bytes + s.uncompressed_bytes,
+                });
+        Ok(total.ratio())
+    }
+
+    /// Get compression types used by chunks in the order of their first
+    /// appearance in the chunk section.
+    ///
+    /// Bags recorded across recorder reconfigurations can contain chunks
+    /// with different compressions. Chunk data does not get decompressed.
+    /// Compressions not supported by the crate are reported once as
+    /// [`Compression::Custom`].
+    pub fn compressions(&self) -> Result<Vec<Compression>> {
+        let mut res = Vec::new();
+        for chunk in self.raw_chunks() {
+            let compression = chunk?.compression;
+            if !res.contains(&compression) {
+                res.push(compression);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Check if all chunks in the bag use the same compression type.
+    ///
+    /// Bags without chunks are considered uniformly compressed.
+    pub fn is_uniformly_compressed(&self) -> Result<bool> {
+        Ok(self.compressions()?.len() <= 1)
+    }
+
+    /// Get total size of chunk data after decompression.
+    ///
+    /// Sizes are read from chunk record headers, so chunks do not get
+    /// decompressed. The value includes `Connection` records stored in
+    /// chunks and headers of `MessageData` records, and is an upper bound of
+    /// memory needed for decompression of all chunks. Unlike the file size,
+    /// it does not depend on the used compression.
+    pub fn total_uncompressed_bytes(&self) -> Result<u64> {
+        Ok(self
+            .compression_stats()?
+            .values()
+            .map(|s| s.uncompressed_bytes)
+            .sum())
+    }
+
+    /// Decompress chunks and decode messages stored in them, measuring
+    /// throughput.
+    ///
+    /// Scan stops after `limit` messages if it is provided. Message payloads
+    /// are not deserialized, so results reflect cost of decompression and
+    /// record parsing.
+    pub fn benchmark_scan(&self, limit: Option<usize>) -> Result<ScanStats> {
+        let limit = limit.unwrap_or(usize::MAX) as u64;
+        let start = Instant::now();
+        let mut res = ScanStats::default();
+        let mut records = self.chunk_records();
+        'chunks: while res.messages < limit {
+            let chunk = match records.next().transpose()? {
+                Some(ChunkRecord::Chunk(chunk)) => chunk,
+                Some(ChunkRecord::IndexData(_)) => continue,
+                None => break,
+            };
+            res.decompressed_bytes += chunk.uncompressed_len as u64;
+            for msg in chunk.messages() {
+                if let MessageRecord::MessageData(_) = msg? {
+                    res.messages += 1;
+                    if res.messages >= limit {
+                        break 'chunks;
+                    }
+                }
+            }
+        }
+        res.elapsed = start.elapsed();
+        Ok(res)
+    }
+
+    /// Get `ChunkInfo` records together with per-topic message counts of
+    /// each chunk.
+    ///
+    /// Connection IDs of chunk info entries are resolved to topics using
+    /// connections from the index section. IDs of connections missing from
+    /// the index are reported as topic names.
+    #[allow(clippy::type_complexity)]
+    pub fn chunk_info_report(&self) -> Result<Vec<(ChunkInfo<'_>, Vec<(String, u32)>)>> {
+        self.require_index()?;
+        let mut buf = Vec::new();
+        let conns = self.connection_map(&mut buf)?;
+        let mut res = Vec::new();
+        for record in self.index_records() {
+            let info = match record? {
+                IndexRecord::ChunkInfo(info) => info,
+                _ => continue,
+            };
+            let counts = info
+                .entries()
+                .map(|e| {
+                    let topic = match conns.get(&e.conn_id) {
+                        Some(conn) => conn.storage_topic.to_string(),
+                        None => e.conn_id.to_string(),
+                    };
+                    (topic, e.count)
+                })
+                .collect();
+            res.push((info, counts));
+        }
+        Ok(res)
+    }
+
+    /// Find intervals longer than `threshold_ns` without messages on `topic`.
+    ///
+    /// Message timestamps are read from `IndexData` records, so chunks do
+    /// not get decompressed. Messages of all connections stored under
+    /// `topic` are considered. Returns `(gap_start, gap_end)` pairs of
+    /// timestamps of consecutive messages, ordered by time.
+    pub fn topic_gaps(&self, topic: &str, threshold_ns: u64) -> Result<Vec<(u64, u64)>> {
+        let times = self.topic_index_times(topic)?;
+        Ok(times
+            .windows(2)
+            .filter(|w| w[1] - w[0] > threshold_ns)
+            .map(|w| (w[0], w[1]))
+            .collect())
+    }
+
+    /// Compare connections declared in the index section with connections
+    /// referenced by `ChunkInfo` records.
+    ///
+    /// Unused and orphaned connections usually indicate a bug in the
+    /// recorder. Only the index section is read.
+    pub fn connection_usage(&self) -> Result<ConnectionUsage> {
+        self.require_index()?;
+        let mut declared = BTreeSet::new();
+        let mut observed = BTreeSet::new();
+        for record in self.index_records() {
+            match record? {
+                IndexRecord::Connection(conn) => {
+                    declared.insert(conn.id);
+                }
+                IndexRecord::ChunkInfo(info) => observed.extend(info.entries().map(|e| e.conn_id)),
+                IndexRecord::IndexData(_) => {}
+            }
+        }
+        Ok(ConnectionUsage {
+            unused: declared.difference(&observed).copied().collect(),
+            orphaned: observed.difference(&declared).copied().collect(),
+            declared: declared.into_iter().collect(),
+            observed: observed.into_iter().collect(),
+        })
+    }
+
+    /// Count messages on `topic` in consecutive time buckets of `bucket_ns`
+    /// nanoseconds.
+    ///
+    /// Buckets are aligned to multiples of `bucket_ns` since UNIX epoch and
+    /// span from the first to the last message on the topic, including
+    /// buckets without messages. Message timestamps are read from
+    /// `IndexData` records, so chunks do not get decompressed. Returns
+    /// `(bucket_start, count)` pairs.
+    ///
+    /// Returns [`Error::InvalidBucketSize`] if `bucket_ns` is equal to zero
+    /// or if the series would contain more than 2^20 buckets.
+    pub fn topic_rate_series(&self, topic: &str, bucket_ns: u64) -> Result<Vec<(u64, u32)>> {
+        let invalid = Error::InvalidBucketSize { bucket_ns };
+        if bucket_ns == 0 {
+            return Err(invalid);
+        }
+        let times = self.topic_index_times(topic)?;
+        let (first, last) = match (times.first(), times.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return Ok(Vec::new()),
+        };
+        let first = first - first % bucket_ns;
+        let n = match ((last - first) / bucket_ns).checked_add(1) {
+            Some(n) if n <= MAX_RATE_BUCKETS => n,
+            _ => return Err(invalid),
+        };
+        // bucket starts do not exceed `last`, so they can not overflow
+        let mut res: Vec<(u64, u32)> = (0..n).map(|i| (first + i * bucket_ns, 0)).collect();
+        for t in times {
+            let count = &mut res[((t - first) / bucket_ns) as usize].1;
+            *count = count.saturating_add(1);
+        }
+        Ok(res)
+    }
+
+    /// Get sorted timestamps of messages on `topic` from `IndexData` records.
+    fn topic_index_times(&self, topic: &str) -> Result<Vec<u64>> {
+        self.require_index()?;
+        let conn_ids: HashSet<u32> = self
+            .connection_map(&mut Vec::new())?
+            .into_iter()
+            .filter(|(_, conn)| conn.storage_topic == topic)
+            .map(|(id, _)| id)
+            .collect();
+        let mut times: Vec<u64> = self
+            .index_entries()?
+            .into_iter()
+            .filter(|l| conn_ids.contains(&l.conn_id))
+            .map(|l| l.time)
+            .collect();
+        times.sort_unstable();
+        Ok(times)
+    }
+}
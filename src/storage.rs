@@ -0,0 +1,21 @@
+use memmap2::Mmap;
+use std::ops::Deref;
+
+/// Bytes of a bag file.
+pub(crate) enum Storage {
+    /// Memory-mapped file
+    Mmap(Mmap),
+    /// File content read into memory
+    Memory(Vec<u8>),
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Mmap(mmap) => mmap,
+            Storage::Memory(buf) => buf,
+        }
+    }
+}
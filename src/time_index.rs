@@ -0,0 +1,213 @@
+use crate::{IndexRecord, MessageLocator, Result, RosBag};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::io::{self, Read};
+
+/// Magic bytes at the beginning of serialized [`BagTimeIndex`].
+const MAGIC: &[u8; 8] = b"RBTIDX\x00\x02";
+
+/// Time span of a chunk stored in [`BagTimeIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeIndexEntry {
+    /// Offset of the chunk record relative to the bag file beginning
+    pub chunk_pos: u64,
+    /// Timestamp of earliest message in the chunk in nanoseconds of UNIX epoch
+    pub start_time: u64,
+    /// Timestamp of latest message in the chunk in nanoseconds of UNIX epoch
+    pub end_time: u64,
+}
+
+/// Index of chunks and messages sorted by time, which allows to find chunk
+/// containing messages with a given timestamp in O(log n) and to jump
+/// directly to messages without reading index records of the bag file.
+///
+/// Built using [`RosBag::build_time_index`].
+#[derive(Debug, Clone, Default)]
+pub struct BagTimeIndex {
+    entries: Vec<TimeIndexEntry>,
+    /// Maximum end time of `entries[..=i]`, used for skipping over chunks
+    /// which end before the searched timestamp
+    max_end: Vec<u64>,
+    /// `(id, storage topic)` pairs sorted by ID
+    connections: Vec<(u32, String)>,
+    /// Message locations sorted by time, chunk position, offset and ID
+    messages: Vec<MessageLocator>,
+}
+
+impl BagTimeIndex {
+    fn new(
+        mut entries: Vec<TimeIndexEntry>,
+        mut connections: Vec<(u32, String)>,
+        mut messages: Vec<MessageLocator>,
+    ) -> Self {
+        entries.sort_by_key(|e| (e.start_time, e.chunk_pos));
+        let max_end = entries
+            .iter()
+            .scan(0, |max, e| {
+                *max = e.end_time.max(*max);
+                Some(*max)
+            })
+            .collect();
+        connections.sort_unstable();
+        connections.dedup_by_key(|(id, _)| *id);
+        messages.sort_unstable_by_key(|m| (m.time, m.chunk_pos, m.offset, m.conn_id));
+        Self {
+            entries,
+            max_end,
+            connections,
+            messages,
+        }
+    }
+
+    /// Find chunk which contains messages with timestamp `t_ns`.
+    ///
+    /// If time ranges of several chunks contain `t_ns`, the chunk with the
+    /// latest start time is returned. Returns `None` if `t_ns` does not fall
+    /// into time range of any chunk.
+    pub fn locate(&self, t_ns: u64) -> Option<&TimeIndexEntry> {
+        let idx = self.entries.partition_point(|e| e.start_time <= t_ns);
+        (0..idx)
+            .rev()
+            .take_while(|&i| self.max_end[i] >= t_ns)
+            .map(|i| &self.entries[i])
+            .find(|e| e.end_time >= t_ns)
+    }
+
+    /// Get chunk entries sorted by start time.
+    pub fn entries(&self) -> &[TimeIndexEntry] {
+        &self.entries
+    }
+
+    /// Get `(id, topic)` pairs of connections sorted by ID.
+    ///
+    /// Topics are taken from [`Connection::storage_topic`][crate::record_types::Connection::storage_topic].
+    pub fn connections(&self) -> &[(u32, String)] {
+        &self.connections
+    }
+
+    /// Get topic of connection with ID `conn_id`.
+    pub fn topic(&self, conn_id: u32) -> Option<&str> {
+        let idx = self
+            .connections
+            .binary_search_by_key(&conn_id, |(id, _)| *id)
+            .ok()?;
+        Some(&self.connections[idx].1)
+    }
+
+    /// Get locations of all messages sorted by time.
+    ///
+    /// Messages with equal timestamps are ordered by their position in the
+    /// file. Locations can be used to seek chunk and message iterators
+    /// directly to the messages.
+    pub fn messages(&self) -> &[MessageLocator] {
+        &self.messages
+    }
+
+    /// Get locations of messages received in the `[start_ns, end_ns]` time
+    /// range, sorted by time.
+    pub fn messages_in_range(&self, start_ns: u64, end_ns: u64) -> &[MessageLocator] {
+        let start = self.messages.partition_point(|m| m.time < start_ns);
+        let end = self.messages.partition_point(|m| m.time <= end_ns);
+        &self.messages[start..end.max(start)]
+    }
+
+    /// Write index to `writer` in a compact binary format.
+    ///
+    /// The format consists of magic bytes followed by chunk entries,
+    /// connections and message locations, each list prefixed by its length.
+    /// Integers are stored in little-endian order, topics as length-prefixed
+    /// UTF-8 strings. It does not identify the bag file, so pair the saved
+    /// index with [`RosBag::index_fingerprint`] to detect stale caches.
+    pub fn save<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u64::<LE>(self.entries.len() as u64)?;
+        for e in &self.entries {
+            writer.write_u64::<LE>(e.chunk_pos)?;
+            writer.write_u64::<LE>(e.start_time)?;
+            writer.write_u64::<LE>(e.end_time)?;
+        }
+        writer.write_u64::<LE>(self.connections.len() as u64)?;
+        for (id, topic) in &self.connections {
+            writer.write_u32::<LE>(*id)?;
+            writer.write_u32::<LE>(topic.len() as u32)?;
+            writer.write_all(topic.as_bytes())?;
+        }
+        writer.write_u64::<LE>(self.messages.len() as u64)?;
+        for m in &self.messages {
+            writer.write_u64::<LE>(m.time)?;
+            writer.write_u64::<LE>(m.chunk_pos)?;
+            writer.write_u32::<LE>(m.offset)?;
+            writer.write_u32::<LE>(m.conn_id)?;
+        }
+        Ok(())
+    }
+
+    /// Read index written by [`BagTimeIndex::save`] from `reader`.
+    pub fn load<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let invalid_data = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("invalid time index magic bytes"));
+        }
+        let n = reader.read_u64::<LE>()?;
+        let mut entries = Vec::new();
+        for _ in 0..n {
+            entries.push(TimeIndexEntry {
+                chunk_pos: reader.read_u64::<LE>()?,
+                start_time: reader.read_u64::<LE>()?,
+                end_time: reader.read_u64::<LE>()?,
+            });
+        }
+        let n = reader.read_u64::<LE>()?;
+        let mut connections = Vec::new();
+        for _ in 0..n {
+            let id = reader.read_u32::<LE>()?;
+            let len = reader.read_u32::<LE>()?;
+            let mut topic = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut topic)?;
+            if topic.len() != len as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let topic = String::from_utf8(topic).map_err(|_| invalid_data("invalid topic"))?;
+            connections.push((id, topic));
+        }
+        let n = reader.read_u64::<LE>()?;
+        let mut messages = Vec::new();
+        for _ in 0..n {
+            messages.push(MessageLocator {
+                time: reader.read_u64::<LE>()?,
+                chunk_pos: reader.read_u64::<LE>()?,
+                offset: reader.read_u32::<LE>()?,
+                conn_id: reader.read_u32::<LE>()?,
+            });
+        }
+        Ok(Self::new(entries, connections, messages))
+    }
+}
+
+impl RosBag {
+    /// Build time index from `ChunkInfo` and `Connection` records stored in
+    /// the index section and `IndexData` records stored in the chunk section.
+    ///
+    /// Chunks do not get decompressed.
+    pub fn build_time_index(&self) -> Result<BagTimeIndex> {
+        self.require_index()?;
+        let mut entries = Vec::with_capacity(self.header.chunk_count as usize);
+        let mut connections = Vec::with_capacity(self.header.conn_count as usize);
+        for record in self.index_records() {
+            match record? {
+                IndexRecord::ChunkInfo(info) => entries.push(TimeIndexEntry {
+                    chunk_pos: info.chunk_pos,
+                    start_time: info.start_time,
+                    end_time: info.end_time,
+                }),
+                IndexRecord::Connection(conn) => {
+                    connections.push((conn.id, conn.storage_topic.to_string()))
+                }
+                IndexRecord::IndexData(_) => {}
+            }
+        }
+        let messages = self.index_entries()?;
+        Ok(BagTimeIndex::new(entries, connections, messages))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::record_types::MessageData;
+use crate::{ChunkRecord, Error, MessageRecord, MessageRecordsIterator, Result, RosBag};
+use std::collections::{BTreeMap, HashMap};
+
+/// Decompressed chunk data and number of pending messages stored in it.
+type PendingChunk = (Vec<u8>, usize);
+
+impl RosBag {
+    /// Group messages into consecutive non-overlapping time windows of
+    /// `window_ns` nanoseconds and call `f` with start of every window and
+    /// its messages.
+    ///
+    /// Windows are aligned to multiples of `window_ns` since UNIX epoch,
+    /// span chunk boundaries and are passed to `f` in time order. Windows
+    /// without messages are skipped. A window is emitted only after a message
+    /// with timestamp past the window end plus `tolerance_ns` was
+    /// encountered, so messages which are stored out of order by no more than
+    /// the tolerance land in the correct window. Messages which arrive after
+    /// their window was emitted are dropped and their number is returned.
+    ///
+    /// Decompressed data of chunks is kept in memory while they contain
+    /// messages of pending windows.
+    ///
+    /// # Panics
+    /// If `window_ns` is equal to zero.
+    pub fn for_each_time_window<F>(
+        &self,
+        window_ns: u64,
+        tolerance_ns: u64,
+        mut f: F,
+    ) -> Result<u64>
+    where
+        F: FnMut(u64, &[MessageData<'_>]),
+    {
+        assert!(window_ns != 0, "window size must not be zero");
+        let mut chunks: HashMap<usize, PendingChunk> = HashMap::new();
+        // window start -> (chunk index, message offset)
+        let mut pending: BTreeMap<u64, Vec<(usize, u32)>> = BTreeMap::new();
+        let mut max_time = 0;
+        let mut emitted_until = 0;
+        let mut late = 0;
+
+        let records = self.chunk_records().filter_map(|r| match r {
+            Ok(ChunkRecord::Chunk(chunk)) => Some(Ok(chunk)),
+            Ok(ChunkRecord::IndexData(_)) => None,
+            Err(err) => Some(Err(err)),
+        });
+        for (idx, chunk) in records.enumerate() {
+            let chunk = chunk?;
+            let mut count = 0;
+            let mut iter = chunk.messages();
+            loop {
+                let offset = iter.cursor.pos() as u32;
+                let msg = match iter.next() {
+                    Some(msg) => msg?,
+                    None => break,
+                };
+                if let MessageRecord::MessageData(msg) = msg {
+                    if msg.time < emitted_until {
+                        late += 1;
+                        continue;
+                    }
+                    let start = msg.time - msg.time % window_ns;
+                    pending.entry(start).or_default().push((idx, offset));
+                    max_time = max_time.max(msg.time);
+                    count += 1;
+                }
+            }
+            if count != 0 {
+                chunks.insert(idx, (chunk.into_data(), count));
+            }
+
+            while let Some(&start) = pending.keys().next() {
+                let end = start.saturating_add(window_ns);
+                if max_time < end.saturating_add(tolerance_ns) {
+                    break;
+                }
+                self.emit_window(start, &mut pending, &mut chunks, &mut f)?;
+                emitted_until = end;
+            }
+        }
+        while let Some(&start) = pending.keys().next() {
+            self.emit_window(start, &mut pending, &mut chunks, &mut f)?;
+        }
+        if late != 0 {
+            log::warn!("dropped {} messages which arrived after their window", late);
+        }
+        Ok(late)
+    }
+
+    /// Pass messages of the window starting at `start` to `f` and release
+    /// chunks which do not contain pending messages anymore.
+    fn emit_window<F>(
+        &self,
+        start: u64,
+        pending: &mut BTreeMap<u64, Vec<(usize, u32)>>,
+        chunks: &mut HashMap<usize, PendingChunk>,
+        f: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(u64, &[MessageData<'_>]),
+    {
+        let entries = pending.remove(&start).unwrap_or_default();
+        {
+            let mut msgs = Vec::with_capacity(entries.len());
+            for &(idx, offset) in &entries {
+                let mut iter = MessageRecordsIterator::new(&chunks[&idx].0);
+                iter.cursor = iter.cursor.with_unknown_field_policy(self.unknown_fields);
+                iter.seek(offset)?;
+                match iter.next().ok_or(Error::InvalidRecord)?? {
+                    MessageRecord::MessageData(msg) => msgs.push(msg),
+                    MessageRecord::Connection(_) => return Err(Error::InvalidRecord),
+                }
+            }
+            msgs.sort_by_key(|m| m.time);
+            f(start, &msgs);
+        }
+        for (idx, _) in entries {
+            let chunk = chunks.get_mut(&idx).expect("chunk is pending");
+            chunk.1 -= 1;
+            if chunk.1 == 0 {
+                chunks.remove(&idx);
+            }
+        }
+        Ok(())
+    }
+}
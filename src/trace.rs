@@ -0,0 +1,19 @@
+//! Optional instrumentation using the `tracing` crate.
+
+/// Enter a `tracing` span which lasts until the returned guard is dropped.
+///
+/// Expands to a zero-sized guard without evaluating span fields if the
+/// `tracing` feature is disabled.
+macro_rules! span {
+    ($lvl:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::span!(tracing::Level::$lvl, $($arg)+).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Guard returned by `span!` if the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
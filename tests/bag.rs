@@ -0,0 +1,2191 @@
+use rosbag::record_types::Compression;
+use rosbag::{
+    BagTimeIndex, ChunkRecord, Error, FieldIterator, IndexRecord, IntegrityEvent, MessageLocator,
+    MessageRecord, OpenOptions, Record, RosBag,
+};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+mod common;
+
+#[test]
+fn empty_chunk_section() {
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+    ]
+    .concat();
+    let path = common::bag("empty", &[], &index, 2, 0);
+    let bag = RosBag::new(&path).unwrap();
+
+    assert_eq!(bag.get_chunk_count(), 0);
+    assert_eq!(bag.chunk_records().count(), 0);
+    assert_eq!(bag.time_range().unwrap(), None);
+    assert!(bag.index_entries().unwrap().is_empty());
+
+    let conns = bag
+        .index_records()
+        .filter(|r| matches!(r, Ok(IndexRecord::Connection(_))))
+        .count();
+    assert_eq!(conns, 2);
+}
+
+#[test]
+fn index_connections_count() {
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("conns", &[], &index, 2, 0)).unwrap();
+    let conns = bag.index_connections().unwrap();
+    assert_eq!(conns.len(), 2);
+    assert_eq!(conns[1].storage_topic, "/imu");
+
+    let bag = RosBag::new(common::bag("conns-mismatch", &[], &index, 3, 0)).unwrap();
+    assert!(matches!(
+        bag.index_connections(),
+        Err(Error::ConnectionCountMismatch {
+            expected: 3,
+            actual: 2
+        })
+    ));
+}
+
+#[test]
+fn message_locators_in_range() {
+    let start = common::chunk_section_start(1, 2);
+    let chunk1 = [common::message(0, 10, b"a"), common::message(0, 20, b"b")].concat();
+    let chunk2 = common::message(0, 30, b"c");
+    let chunks = [
+        common::chunk(&chunk1),
+        common::index_data(0, &[(10, 0), (20, 30)]),
+    ]
+    .concat();
+    let chunk2_pos = start + chunks.len() as u64;
+    let chunks = [
+        chunks,
+        common::chunk(&chunk2),
+        common::index_data(0, &[(30, 0)]),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 20, &[(0, 2)]),
+        common::chunk_info(chunk2_pos, 30, 30, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("locators", &chunks, &index, 1, 2)).unwrap();
+
+    assert_eq!(bag.index_entries().unwrap().len(), 3);
+    let locators = bag.message_locators_in_range(15, 30).unwrap();
+    assert_eq!(
+        locators,
+        [
+            MessageLocator {
+                chunk_pos: start,
+                offset: 30,
+                conn_id: 0,
+                time: 20
+            },
+            MessageLocator {
+                chunk_pos: chunk2_pos,
+                offset: 0,
+                conn_id: 0,
+                time: 30
+            },
+        ]
+    );
+    assert!(bag.message_locators_in_range(21, 29).unwrap().is_empty());
+}
+
+#[test]
+fn load_all() {
+    let chunk = [
+        common::connection(0, "/chatter"),
+        common::message(0, 10, b"a"),
+        common::message(0, 20, b"b"),
+    ]
+    .concat();
+    let start = common::chunk_section_start(1, 1);
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 20, &[(0, 2)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag(
+        "load-all",
+        &common::chunk(&chunk),
+        &index,
+        1,
+        1,
+    ))
+    .unwrap();
+    let loaded = bag.load_all().unwrap();
+    drop(bag);
+
+    assert_eq!(loaded.get_chunk_count(), 1);
+    for _ in 0..2 {
+        let msgs: Vec<_> = loaded.messages().collect::<Result<_, _>>().unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[1].time, 20);
+        assert_eq!(msgs[1].data, b"b");
+    }
+    let conns: Vec<_> = loaded.connections().collect::<Result<_, _>>().unwrap();
+    assert_eq!(conns.len(), 1);
+    assert_eq!(conns[0].topic, "/chatter");
+}
+
+#[test]
+fn implausible_lengths() {
+    let path = common::bag("implausible", &[], &[], 0, 0);
+    let mut buf = std::fs::read(&path).unwrap();
+    buf.extend_from_slice(&0x1000_0000u32.to_be_bytes());
+    std::fs::write(&path, &buf).unwrap();
+    let bag = RosBag::new(&path).unwrap();
+    assert!(matches!(
+        bag.index_records().next(),
+        Some(Err(Error::ImplausibleLength {
+            declared: 0x10,
+            available: 0
+        }))
+    ));
+
+    let chunks = common::message(0, 0, b"a");
+    let mut buf = std::fs::read(common::bag("implausible-pos", &chunks, &[], 0, 0)).unwrap();
+    buf.truncate(buf.len() - 1);
+    std::fs::write(&path, &buf).unwrap();
+    let err = RosBag::new(&path).err().unwrap();
+    let err = err.into_inner().unwrap().downcast::<Error>().unwrap();
+    assert!(matches!(*err, Error::InvalidIndexPosition { .. }));
+}
+
+#[test]
+fn messages_resolved() {
+    let chunk = [
+        common::message(1, 5, b"x"),
+        common::connection(0, "/chatter"),
+        common::message(0, 10, b"a"),
+        common::message(1, 20, b"b"),
+    ]
+    .concat();
+    let start = common::chunk_section_start(2, 1);
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+        common::chunk_info(start, 5, 20, &[(0, 1), (1, 2)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag(
+        "resolved",
+        &common::chunk(&chunk),
+        &index,
+        2,
+        1,
+    ))
+    .unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    let topics: Vec<_> = chunk
+        .messages_resolved()
+        .map(|res| res.map(|(conn, msg)| (conn.map(|c| c.topic), msg.time)))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(topics, [(None, 5), (Some("/chatter"), 10), (None, 20)]);
+}
+
+#[test]
+fn benchmark_scan() {
+    let chunk = [common::message(0, 10, b"a"), common::message(0, 20, b"b")].concat();
+    let chunks = [common::chunk(&chunk), common::chunk(&chunk)].concat();
+    let bag = RosBag::new(common::bag("bench", &chunks, &[], 0, 2)).unwrap();
+
+    assert_eq!(
+        bag.total_uncompressed_bytes().unwrap(),
+        2 * chunk.len() as u64
+    );
+    let stats = bag.benchmark_scan(None).unwrap();
+    assert_eq!(stats.messages, 4);
+    assert_eq!(stats.decompressed_bytes, 2 * chunk.len() as u64);
+    let stats = bag.benchmark_scan(Some(1)).unwrap();
+    assert_eq!(stats.messages, 1);
+    assert_eq!(stats.decompressed_bytes, chunk.len() as u64);
+    assert_eq!(bag.benchmark_scan(Some(0)).unwrap().decompressed_bytes, 0);
+}
+
+#[test]
+fn custom_decompressor() {
+    let data = common::message(0, 10, b"a");
+    // compressed data has an extra leading byte, so its length differs
+    let reversed: Vec<u8> = [0].iter().chain(data.iter().rev()).copied().collect();
+    let chunk = common::record(
+        &[
+            ("op", &[0x05]),
+            ("compression", b"rev"),
+            ("size", &(data.len() as u32).to_le_bytes()),
+        ],
+        &reversed,
+    );
+    let path = common::bag("custom-compression", &chunk, &[], 0, 1);
+
+    let bag = RosBag::new(&path).unwrap();
+    assert!(matches!(
+        bag.chunk_records().next(),
+        Some(Err(Error::UnsupportedCompression(name))) if name == "rev"
+    ));
+
+    let bag = OpenOptions::new()
+        .register_decompressor(
+            "rev",
+            Arc::new(|data: &[u8], _| Ok(data[1..].iter().rev().copied().collect())),
+        )
+        .open(&path)
+        .unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    assert_eq!(chunk.compression, Compression::Custom);
+    let name = chunk
+        .header_fields()
+        .map(Result::unwrap)
+        .find(|(name, _)| *name == "compression");
+    assert_eq!(name, Some(("compression", &b"rev"[..])));
+    assert_eq!(chunk.messages().count(), 1);
+    assert_eq!(chunk.compressed_len, reversed.len() as u32);
+    assert_eq!(chunk.uncompressed_len, data.len() as u32);
+}
+
+#[test]
+fn chunk_lengths() {
+    let data = [common::message(0, 10, b"a"), common::message(0, 20, b"bc")].concat();
+    let buf = common::bag_bytes(&common::chunk(&data), &[], 0, 1);
+    let bag = common::open(&buf).unwrap();
+    let chunk = common::first_chunk(&bag);
+    assert_eq!(chunk.compressed_len, data.len() as u32);
+    assert_eq!(chunk.uncompressed_len, data.len() as u32);
+
+    let buf = common::bag_bytes(&common::chunk(&[]), &[], 0, 1);
+    let bag = common::open(&buf).unwrap();
+    let chunk = common::first_chunk(&bag);
+    assert_eq!((chunk.compressed_len, chunk.uncompressed_len), (0, 0));
+}
+
+#[test]
+fn seek_validated() {
+    let start = common::chunk_section_start(0, 1);
+    let chunk = common::chunk(&common::message(0, 10, b"a"));
+    let chunks = [chunk.clone(), common::index_data(0, &[(10, 0)])].concat();
+    let bag = RosBag::new(common::bag("seek-validated", &chunks, &[], 0, 1)).unwrap();
+
+    let mut iter = bag.chunk_records();
+    assert!(iter.seek_validated(start + 1).is_err());
+    assert!(iter.seek_validated(start - 1).is_err());
+    iter.seek_validated(start + chunk.len() as u64).unwrap();
+    assert!(matches!(iter.next(), Some(Ok(ChunkRecord::IndexData(_)))));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn chunk_info_report() {
+    let start = common::chunk_section_start(1, 1);
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 20, &[(0, 2), (5, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("chunk-info-report", &[], &index, 1, 1)).unwrap();
+    let report = bag.chunk_info_report().unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].0.chunk_pos, start);
+    assert_eq!(
+        report[0].1,
+        [("/chatter".to_string(), 2), ("5".to_string(), 1)]
+    );
+}
+
+#[test]
+fn topic_gaps() {
+    let chunks = [
+        common::chunk(&[]),
+        common::index_data(0, &[(10, 0), (50, 0), (20, 0)]),
+        common::index_data(1, &[(30, 0)]),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("topic-gaps", &chunks, &index, 2, 1)).unwrap();
+    assert_eq!(bag.topic_gaps("/chatter", 10).unwrap(), [(20, 50)]);
+    assert_eq!(bag.topic_gaps("/chatter", 5).unwrap(), [(10, 20), (20, 50)]);
+    assert!(bag.topic_gaps("/imu", 0).unwrap().is_empty());
+
+    let series = bag.topic_rate_series("/chatter", 20).unwrap();
+    assert_eq!(series, [(0, 1), (20, 1), (40, 1)]);
+    let series = bag.topic_rate_series("/chatter", 100).unwrap();
+    assert_eq!(series, [(0, 3)]);
+    assert!(bag.topic_rate_series("/none", 10).unwrap().is_empty());
+    assert!(matches!(
+        bag.topic_rate_series("/chatter", 0),
+        Err(Error::InvalidBucketSize { bucket_ns: 0 })
+    ));
+
+    let chunks = [
+        common::chunk(&[]),
+        common::index_data(0, &[(0, 0), (u32::MAX as u64 * 1_000_000_000, 0)]),
+    ]
+    .concat();
+    let index = common::connection(0, "/chatter");
+    let bag = RosBag::new(common::bag("rate-series-span", &chunks, &index, 1, 1)).unwrap();
+    assert!(matches!(
+        bag.topic_rate_series("/chatter", 1),
+        Err(Error::InvalidBucketSize { bucket_ns: 1 })
+    ));
+    let series = bag.topic_rate_series("/chatter", u64::MAX).unwrap();
+    assert_eq!(series, [(0, 2)]);
+}
+
+#[test]
+fn indexed_chunks_reordered() {
+    let start = common::chunk_section_start(1, 2);
+    let chunk1 = common::chunk(&common::message(0, 30, b"late"));
+    let chunk2 = common::chunk(&common::message(0, 10, b"early"));
+    let chunk2_pos = start + chunk1.len() as u64;
+    let chunks = [chunk1, chunk2].concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(chunk2_pos, 10, 10, &[(0, 1)]),
+        common::chunk_info(start, 30, 30, &[(0, 1)]),
+        common::chunk_info(chunk2_pos, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("reordered", &chunks, &index, 1, 2)).unwrap();
+
+    let mut times = Vec::new();
+    for chunk in bag.indexed_chunks().unwrap() {
+        for msg in chunk.unwrap().messages() {
+            if let MessageRecord::MessageData(msg) = msg.unwrap() {
+                times.push(msg.time);
+            }
+        }
+    }
+    assert_eq!(times, [30, 10]);
+}
+
+#[test]
+fn process_parallel() {
+    let mut chunks = Vec::new();
+    for i in 0..10u64 {
+        let msgs: Vec<u8> = (0..3)
+            .flat_map(|j| common::message(0, 3 * i + j, b"m"))
+            .collect();
+        chunks.extend(common::chunk(&msgs));
+    }
+    let bag = RosBag::new(common::bag("parallel", &chunks, &[], 0, 10)).unwrap();
+    for concurrency in [1, 4, 16] {
+        let times: Vec<u64> = bag
+            .process_parallel(|msg| msg.time, concurrency)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(times, (0..30).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn header() {
+    let chunks = common::chunk(&[]);
+    let bag = RosBag::new(common::bag("header", &chunks, &[], 0, 1)).unwrap();
+    let start = common::chunk_section_start(0, 1);
+    let header = bag.header();
+    assert_eq!(header.index_pos, start + chunks.len() as u64);
+    assert_eq!((header.conn_count, header.chunk_count), (0, 1));
+    assert_eq!(bag.chunk_section_range(), start..header.index_pos);
+}
+
+#[test]
+fn tolerate_missing_index() {
+    let data = [
+        common::connection(0, "/chatter"),
+        common::message(0, 10, b"a"),
+    ]
+    .concat();
+    let chunks = common::chunk(&data);
+    let index = [common::connection(0, "/chatter")].concat();
+    let path = common::bag("missing-index", &chunks, &index, 1, 1);
+    let mut buf = std::fs::read(&path).unwrap();
+
+    // truncated index
+    buf.truncate(buf.len() - 1);
+    std::fs::write(&path, &buf).unwrap();
+    let bag = OpenOptions::new()
+        .tolerate_missing_index(true)
+        .open(&path)
+        .unwrap();
+    assert!(!bag.has_index());
+    assert_eq!(bag.index_records().count(), 0);
+    assert_eq!(bag.chunk_records().count(), 1);
+    let missing = |res: Result<(), Error>| matches!(res, Err(Error::MissingIndex));
+    assert!(missing(bag.time_range().map(drop)));
+    assert!(missing(bag.time_range_fast(false).map(drop)));
+    assert!(missing(bag.index_connections().map(drop)));
+    assert!(missing(bag.message_locators_in_range(0, 100).map(drop)));
+    assert!(missing(bag.nth_message("/chatter", 0, |_| ()).map(drop)));
+    assert!(missing(bag.topic_gaps("/chatter", 0).map(drop)));
+    assert!(missing(bag.build_time_index().map(drop)));
+    // chunk section is still readable
+    assert_eq!(bag.index_entries().unwrap().len(), 0);
+    assert_eq!(bag.take_messages(1, |_| ()).unwrap(), 1);
+    // connections are read from chunks
+    let (def, _) = bag.topic_schema("/chatter").unwrap().unwrap();
+    assert_eq!(def, "string data\n");
+    assert_eq!(bag.is_topic_latched("/chatter").unwrap(), Some(false));
+    assert_eq!(bag.topic_schema("/imu").unwrap(), None);
+    assert!(bag.caller_ids().unwrap().is_empty());
+    let types = bag.message_types().unwrap();
+    assert_eq!(types.len(), 1);
+    assert_eq!(types[0].0, "std_msgs/String");
+    let mut stamps = 0;
+    bag.for_each_message_with_stamp(|_, _| stamps += 1).unwrap();
+    assert_eq!(stamps, 1);
+
+    // unset index position
+    buf.truncate(buf.len() - index.len() + 1);
+    let pos = buf.windows(9).position(|w| w == b"index_pos").unwrap() + 10;
+    buf[pos..pos + 8].copy_from_slice(&0u64.to_le_bytes());
+    std::fs::write(&path, &buf).unwrap();
+    assert!(RosBag::new(&path).is_err());
+    let bag = OpenOptions::new()
+        .tolerate_missing_index(true)
+        .open(&path)
+        .unwrap();
+    assert!(!bag.has_index());
+    assert_eq!(bag.chunk_records().count(), 1);
+    assert_eq!(bag.header().index_pos, 0);
+    assert_eq!(bag.header().conn_count, 1);
+    assert_eq!(bag.chunk_section_range().end, buf.len() as u64);
+}
+
+#[test]
+fn header_fields() {
+    let msg = common::record(
+        &[
+            ("op", &[0x02]),
+            ("conn", &0u32.to_le_bytes()),
+            ("time", &common::time(10)),
+            ("vendor", b"ext"),
+        ],
+        b"a",
+    );
+    let bag = RosBag::new(common::bag(
+        "header-fields",
+        &common::chunk(&msg),
+        &[],
+        0,
+        1,
+    ))
+    .unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    let msg = match chunk.messages().next().unwrap().unwrap() {
+        MessageRecord::MessageData(msg) => msg,
+        _ => panic!("expected message"),
+    };
+    let vendor = msg
+        .header_fields()
+        .map(Result::unwrap)
+        .find(|(name, _)| *name == "vendor");
+    assert_eq!(vendor, Some(("vendor", &b"ext"[..])));
+}
+
+#[test]
+fn take_messages() {
+    let chunk =
+        common::chunk(&[common::message(0, 10, b"a"), common::message(0, 20, b"b")].concat());
+    let mut chunks = chunk.clone();
+    // the second chunk is corrupted and must not be read
+    chunks.extend_from_slice(&chunk[..chunk.len() - 1]);
+    let bag = RosBag::new(common::bag("take-messages", &chunks, &[], 0, 2)).unwrap();
+
+    let mut times = Vec::new();
+    assert_eq!(bag.take_messages(2, |msg| times.push(msg.time)).unwrap(), 2);
+    assert_eq!(times, [10, 20]);
+    assert!(bag.take_messages(3, |_| {}).is_err());
+    assert_eq!(bag.take_messages(0, |_| {}).unwrap(), 0);
+}
+
+#[test]
+fn empty_message_payload() {
+    let chunk =
+        common::chunk(&[common::message(3, 10, b""), common::message(4, 20, b"a")].concat());
+    let bag = RosBag::new(common::bag("empty-payload", &chunk, &[], 0, 1)).unwrap();
+    let mut msgs = Vec::new();
+    bag.take_messages(2, |msg| {
+        msgs.push((msg.conn_id, msg.time, msg.data.to_vec()))
+    })
+    .unwrap();
+    assert_eq!(msgs, [(3, 10, vec![]), (4, 20, b"a".to_vec())]);
+
+    let mut lens = Vec::new();
+    bag.take_messages(2, |msg| lens.push(msg.record_len()))
+        .unwrap();
+    let expected = [
+        common::message(3, 10, b"").len() as u64,
+        common::message(4, 20, b"a").len() as u64,
+    ];
+    assert_eq!(lens, expected);
+}
+
+#[test]
+fn connection_usage() {
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+        common::chunk_info(0, 0, 0, &[(0, 1), (7, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("conn-usage", &[], &index, 2, 1)).unwrap();
+    let usage = bag.connection_usage().unwrap();
+    assert_eq!(usage.declared, [0, 1]);
+    assert_eq!(usage.observed, [0, 7]);
+    assert_eq!(usage.unused, [1]);
+    assert_eq!(usage.orphaned, [7]);
+}
+
+#[test]
+fn chunk_at_position() {
+    let start = common::chunk_section_start(0, 2);
+    let chunk = common::chunk(&common::message(0, 10, b"a"));
+    let chunk2_pos = start + chunk.len() as u64;
+    let chunks = [chunk.clone(), chunk.clone()].concat();
+    let index = [
+        common::chunk_info(start, 10, 10, &[]),
+        common::chunk_info(chunk2_pos, 10, 10, &[]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("chunk-at-pos", &chunks, &index, 0, 2)).unwrap();
+
+    let pos = |p| bag.chunk_at_position(p).unwrap().map(|info| info.chunk_pos);
+    assert_eq!(pos(start - 1), None);
+    assert_eq!(pos(start), Some(start));
+    assert_eq!(pos(chunk2_pos - 1), Some(start));
+    assert_eq!(pos(chunk2_pos + 5), Some(chunk2_pos));
+    assert_eq!(pos(chunk2_pos + chunk.len() as u64), None);
+}
+
+#[test]
+fn time_index_save_load() {
+    let index = [
+        common::chunk_info(200, 30, 40, &[]),
+        common::chunk_info(100, 10, 20, &[]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("time-index", &[], &index, 0, 2)).unwrap();
+    let index = bag.build_time_index().unwrap();
+
+    let mut buf = Vec::new();
+    index.save(&mut buf).unwrap();
+    let loaded = BagTimeIndex::load(&buf[..]).unwrap();
+    assert_eq!(loaded.entries(), index.entries());
+    assert_eq!(loaded.locate(35).unwrap().chunk_pos, 200);
+
+    assert!(BagTimeIndex::load(&buf[1..]).is_err());
+    assert!(BagTimeIndex::load(&buf[..buf.len() - 1]).is_err());
+}
+
+#[test]
+fn time_index_messages() {
+    let m0 = common::message(0, 10, b"a");
+    let m1 = common::message(1, 10, b"x");
+    let chunk1 = common::chunk(&[m0.clone(), m1].concat());
+    let idx1 = [
+        common::index_data(0, &[(10, 0)]),
+        common::index_data(1, &[(10, m0.len() as u32)]),
+    ]
+    .concat();
+    let chunks = [
+        chunk1.clone(),
+        idx1.clone(),
+        common::chunk(&common::message(0, 5, b"b")),
+        common::index_data(0, &[(5, 0)]),
+    ]
+    .concat();
+    let start = common::chunk_section_start(2, 2);
+    let second = start + (chunk1.len() + idx1.len()) as u64;
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/other"),
+        common::chunk_info(start, 10, 10, &[(0, 1), (1, 1)]),
+        common::chunk_info(second, 5, 5, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("time-index-msgs", &chunks, &index, 2, 2)).unwrap();
+    let mut buf = Vec::new();
+    bag.build_time_index().unwrap().save(&mut buf).unwrap();
+    let loaded = BagTimeIndex::load(&buf[..]).unwrap();
+
+    let conns = [(0, "/chatter".to_string()), (1, "/other".to_string())];
+    assert_eq!(loaded.connections(), &conns);
+    assert_eq!(loaded.topic(1), Some("/other"));
+    assert_eq!(loaded.topic(2), None);
+    let times: Vec<_> = loaded
+        .messages()
+        .iter()
+        .map(|m| (m.time, m.conn_id))
+        .collect();
+    assert_eq!(times, [(5, 0), (10, 0), (10, 1)]);
+    assert_eq!(loaded.messages_in_range(6, 10).len(), 2);
+    assert!(loaded.messages_in_range(11, 20).is_empty());
+
+    let read = |loc: &MessageLocator| {
+        let mut chunks = bag.chunk_records();
+        chunks.seek(loc.chunk_pos).unwrap();
+        let chunk = match chunks.next().unwrap().unwrap() {
+            ChunkRecord::Chunk(chunk) => chunk,
+            _ => panic!("expected chunk"),
+        };
+        let mut msgs = chunk.messages();
+        msgs.seek(loc.offset).unwrap();
+        match msgs.next().unwrap().unwrap() {
+            MessageRecord::MessageData(msg) => msg.data.to_vec(),
+            _ => panic!("expected message"),
+        }
+    };
+    let data: Vec<_> = loaded.messages().iter().map(read).collect();
+    assert_eq!(data, [b"b", b"a", b"x"]);
+}
+
+#[test]
+fn time_index_overlapping_chunks() {
+    let index = [
+        common::chunk_info(100, 0, 100, &[]),
+        common::chunk_info(200, 10, 20, &[]),
+        common::chunk_info(300, 30, 40, &[]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("time-index-overlap", &[], &index, 0, 3)).unwrap();
+    let index = bag.build_time_index().unwrap();
+
+    assert_eq!(index.locate(50).unwrap().chunk_pos, 100);
+    assert_eq!(index.locate(15).unwrap().chunk_pos, 200);
+    assert_eq!(index.locate(35).unwrap().chunk_pos, 300);
+    assert!(index.locate(101).is_none());
+}
+
+#[test]
+fn duplicate_op() {
+    let msg = common::record(
+        &[
+            ("op", &[0x02]),
+            ("conn", &0u32.to_le_bytes()),
+            ("time", &common::time(10)),
+            ("op", &[0x02]),
+        ],
+        b"a",
+    );
+    let bag = RosBag::new(common::bag("duplicate-op", &common::chunk(&msg), &[], 0, 1)).unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    assert!(matches!(
+        chunk.messages().next(),
+        Some(Err(Error::InvalidRecord))
+    ));
+}
+
+#[test]
+fn concatenated_bags() {
+    let index = common::connection(0, "/chatter");
+    let first = std::fs::read(common::bag("concat-1", &[], &index, 1, 0)).unwrap();
+    let path = common::bag("concat", &[], &index, 1, 0);
+    std::fs::write(&path, [first.clone(), first.clone()].concat()).unwrap();
+
+    let bag = RosBag::new(&path).unwrap();
+    let mut records = bag.index_records();
+    assert!(matches!(
+        records.next(),
+        Some(Ok(IndexRecord::Connection(_)))
+    ));
+    let pos = first.len() as u64;
+    assert!(matches!(records.next(), Some(Err(Error::ConcatenatedBag { pos: p })) if p == pos));
+    assert!(records.next().is_none());
+
+    let is_concatenated = |e: &Error| matches!(e, Error::ConcatenatedBag { pos: p } if *p == pos);
+    assert!(is_concatenated(&bag.time_range_fast(false).unwrap_err()));
+    assert!(is_concatenated(&bag.load_all().unwrap_err()));
+    let raw_err = bag.raw_records().find_map(|r| r.err()).unwrap();
+    assert!(is_concatenated(&raw_err));
+    let scan_err = bag.scan_integrity().find_map(|e| match e {
+        IntegrityEvent::RecordError { error, .. } => Some(error),
+        _ => None,
+    });
+    assert!(is_concatenated(&scan_err.unwrap()));
+}
+
+#[test]
+fn from_reader() {
+    let chunks = common::chunk(&common::message(0, 10, b"a"));
+    let buf = std::fs::read(common::bag("from-reader", &chunks, &[], 0, 1)).unwrap();
+    let bag = RosBag::from_reader(&buf[..], buf.len() as u64).unwrap();
+    assert_eq!(
+        bag.take_messages(1, |msg| assert_eq!(msg.data, b"a"))
+            .unwrap(),
+        1
+    );
+    assert!(RosBag::from_reader(&buf[..], buf.len() as u64 + 1).is_err());
+}
+
+#[test]
+fn recoverable_scan() {
+    let msgs = [common::message(0, 10, b"a"), common::message(0, 20, b"b")].concat();
+    let chunk = common::chunk(&msgs);
+    let mut chunks = [chunk.clone(), chunk].concat();
+    // truncate the last message of the second chunk
+    chunks.truncate(chunks.len() - 1);
+    let path = common::bag("recoverable", &chunks, &[], 0, 2);
+    let mut buf = std::fs::read(&path).unwrap();
+    let pos = buf.windows(9).position(|w| w == b"index_pos").unwrap() + 10;
+    buf[pos..pos + 8].copy_from_slice(&0u64.to_le_bytes());
+    std::fs::write(&path, &buf).unwrap();
+    let bag = OpenOptions::new()
+        .tolerate_missing_index(true)
+        .open(&path)
+        .unwrap();
+
+    let mut times = Vec::new();
+    let report = bag.recoverable_scan(|record| {
+        if let MessageRecord::MessageData(msg) = record {
+            times.push(msg.time);
+        }
+    });
+    assert_eq!(times, [10, 20, 10]);
+    assert_eq!(report.messages, 3);
+    assert_eq!(report.damaged_chunks, 1);
+    assert_eq!(
+        report.unreadable_bytes,
+        common::message(0, 20, b"b").len() as u64 - 1
+    );
+}
+
+#[test]
+fn lenient_messages() {
+    let data = [
+        common::message(0, 10, b"a"),
+        b"\xff\xff\xff\xffgarbage".to_vec(),
+        common::message(0, 20, b"b"),
+    ]
+    .concat();
+    let path = common::bag("lenient", &common::chunk(&data), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+
+    assert_eq!(chunk.messages().count(), 2);
+    let items: Vec<_> = chunk
+        .lenient_messages()
+        .map(|r| match r {
+            Ok(MessageRecord::MessageData(msg)) => Some(msg.time),
+            Ok(_) => panic!("unexpected connection"),
+            Err(_) => None,
+        })
+        .collect();
+    assert_eq!(items, [Some(10), None, Some(20)]);
+}
+
+#[test]
+fn message_to_record_bytes() {
+    let msg = common::message(3, 1_500_000_042, b"payload");
+    let path = common::bag("to_record_bytes", &common::chunk(&msg), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    match chunk.messages().next().unwrap().unwrap() {
+        MessageRecord::MessageData(data) => assert_eq!(data.to_record_bytes(), msg),
+        _ => panic!("expected message"),
+    }
+}
+
+#[test]
+fn compression_decompress() {
+    use std::io::Write;
+
+    let data = b"some chunk data";
+    let mut enc = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    enc.write_all(data).unwrap();
+    let compressed = enc.finish().unwrap();
+    let res = Compression::Bzip2.decompress(&compressed, Some(data.len() as u32));
+    assert_eq!(res.unwrap(), data);
+
+    assert_eq!(Compression::None.decompress(data, None).unwrap(), data);
+    assert!(matches!(
+        Compression::None.decompress(data, Some(1)),
+        Err(Error::DecompressionSizeMismatch { expected: 1, .. })
+    ));
+    assert!(matches!(
+        Compression::Custom.decompress(data, None),
+        Err(Error::UnsupportedCompression(_))
+    ));
+}
+
+#[test]
+fn raw_chunks() {
+    let chunk = common::chunk(&common::message(0, 10, b"a"));
+    let index = common::index_data(0, &[(10, 0)]);
+    let chunks = [chunk.clone(), index, chunk].concat();
+    let path = common::bag("raw_chunks", &chunks, &[], 0, 2);
+    let bag = RosBag::new(&path).unwrap();
+    let start = common::chunk_section_start(0, 2);
+
+    let raw: Vec<_> = bag.raw_chunks().collect::<Result<_, _>>().unwrap();
+    assert_eq!(raw.len(), 2);
+    assert_eq!(raw[0].chunk_pos, start);
+    for chunk in &raw {
+        assert_eq!(chunk.compression, Compression::None);
+        assert_eq!(chunk.compressed, common::message(0, 10, b"a"));
+        assert_eq!(chunk.uncompressed_size, chunk.compressed.len() as u32);
+    }
+}
+
+#[test]
+fn mixed_compressions() {
+    let msg = common::message(0, 10, b"a");
+    let custom = common::record(
+        &[
+            ("op", &[0x05]),
+            ("compression", b"rev"),
+            ("size", &(msg.len() as u32).to_le_bytes()),
+        ],
+        &msg,
+    );
+    let path = common::bag("uniform", &common::chunk(&msg), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    assert_eq!(bag.compressions().unwrap(), [Compression::None]);
+    assert!(bag.is_uniformly_compressed().unwrap());
+
+    let chunks = [common::chunk(&msg), custom].concat();
+    let path = common::bag("mixed", &chunks, &[], 0, 2);
+    let bag = RosBag::new(&path).unwrap();
+    let expected = [Compression::None, Compression::Custom];
+    assert_eq!(bag.compressions().unwrap(), expected);
+    assert!(!bag.is_uniformly_compressed().unwrap());
+}
+
+#[test]
+fn chunks_with_following_index() {
+    let msgs = [common::message(0, 10, b"a"), common::message(1, 20, b"b")].concat();
+    let chunks = [
+        common::chunk(&msgs),
+        common::index_data(0, &[(10, 0)]),
+        common::index_data(1, &[(20, common::message(0, 10, b"a").len() as u32)]),
+        common::chunk(&common::message(0, 30, b"c")),
+        common::index_data(0, &[(30, 0)]),
+    ]
+    .concat();
+    let path = common::bag("with_following_index", &chunks, &[], 0, 2);
+    let bag = RosBag::new(&path).unwrap();
+
+    let kinds: Vec<_> = bag
+        .chunk_records()
+        .map(|r| rosbag::Record::from(r.unwrap()).kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        ["Chunk", "IndexData", "IndexData", "Chunk", "IndexData"]
+    );
+
+    let groups: Vec<_> = bag
+        .chunk_records()
+        .with_following_index()
+        .map(|r| {
+            let (chunk, index) = r.unwrap();
+            let conns: Vec<_> = index.iter().map(|i| i.conn_id).collect();
+            (chunk.messages().count(), conns)
+        })
+        .collect();
+    assert_eq!(groups, [(2, vec![0, 1]), (1, vec![0])]);
+}
+
+#[test]
+fn chunks_with_following_index_error() {
+    let unknown = common::record(&[("op", &[0x42])], b"");
+    let chunks = [
+        common::chunk(&common::message(0, 10, b"a")),
+        common::index_data(0, &[(10, 0)]),
+        unknown,
+    ]
+    .concat();
+    let path = common::bag("with_following_index_error", &chunks, &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+
+    let mut groups = bag.chunk_records().with_following_index();
+    let (chunk, index) = groups.next().unwrap().unwrap();
+    assert_eq!(chunk.messages().count(), 1);
+    assert_eq!(index.len(), 1);
+    assert!(matches!(
+        groups.next(),
+        Some(Err(Error::UnknownRecord(0x42)))
+    ));
+    assert!(groups.next().is_none());
+}
+
+#[test]
+fn format_time_and_duration() {
+    use rosbag::record_types::utils::{format_duration, format_time};
+
+    assert_eq!(format_time(0), "1970-01-01T00:00:00.000000000Z");
+    assert_eq!(
+        format_time(1_614_834_367_123_456_789),
+        "2021-03-04T05:06:07.123456789Z"
+    );
+    assert_eq!(
+        format_time(951_782_400_000_000_000),
+        "2000-02-29T00:00:00.000000000Z"
+    );
+
+    assert_eq!(format_duration(0), "0s");
+    assert_eq!(format_duration(4_500_000_000), "4.5s");
+    assert_eq!(format_duration(5_004_500_000_000), "1h23m24.5s");
+    assert_eq!(format_duration(120_000_000_001), "2m0.000000001s");
+}
+
+#[test]
+fn messages_grouped() {
+    let m0 = common::message(0, 10, b"a");
+    let m1 = common::message(1, 15, b"b");
+    let m2 = common::message(0, 20, b"c");
+    let chunks = [
+        common::chunk(&[m0.clone(), m1].concat()),
+        common::index_data(0, &[(10, 0)]),
+        common::index_data(1, &[(15, m0.len() as u32)]),
+        common::chunk(&m2),
+        common::index_data(0, &[(20, 0)]),
+    ]
+    .concat();
+    let index = [
+        common::connection(1, "/second"),
+        common::connection(0, "/first"),
+    ]
+    .concat();
+    let path = common::bag("messages_grouped", &chunks, &index, 2, 2);
+    let bag = RosBag::new(&path).unwrap();
+
+    let mut seen = Vec::new();
+    bag.messages_grouped(|conn, msg| seen.push((conn.topic.to_string(), msg.time)))
+        .unwrap();
+    let topic = |t: &str| t.to_string();
+    assert_eq!(
+        seen,
+        [
+            (topic("/second"), 15),
+            (topic("/first"), 10),
+            (topic("/first"), 20)
+        ]
+    );
+
+    let loaded = bag.load_all().unwrap();
+    let groups = loaded.messages_by_connection().unwrap();
+    let times = |id| groups[&id].iter().map(|m| m.time).collect::<Vec<_>>();
+    assert_eq!(times(0), [10, 20]);
+    assert_eq!(times(1), [15]);
+}
+
+#[test]
+fn legacy_message_definition_skipped() {
+    let legacy = common::record(&[("op", &[0x01]), ("topic", b"/chatter")], b"");
+    let data = [legacy.clone(), common::message(0, 10, b"a")].concat();
+    let chunks = [legacy, common::chunk(&data)].concat();
+    let path = common::bag("legacy_op", &chunks, &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+
+    let records: Vec<_> = bag.chunk_records().collect::<Result<_, _>>().unwrap();
+    assert_eq!(records.len(), 1);
+    let chunk = match &records[0] {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    let msgs: Vec<_> = chunk.messages().collect::<Result<_, _>>().unwrap();
+    assert_eq!(msgs.len(), 1);
+}
+
+#[test]
+fn legacy_message_definition_in_index() {
+    let legacy = common::record(&[("op", &[0x01]), ("topic", b"/chatter")], b"");
+    let chunk = common::chunk(&common::message(0, 10, b"a"));
+    let chunks = [
+        legacy.clone(),
+        chunk.clone(),
+        legacy,
+        common::index_data(0, &[(10, 0)]),
+    ]
+    .concat();
+    let start = common::chunk_section_start(1, 1);
+    let chunk_pos =
+        start + common::record(&[("op", &[0x01]), ("topic", b"/chatter")], b"").len() as u64;
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(chunk_pos, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("legacy_index", &chunks, &index, 1, 1)).unwrap();
+
+    let expected = [MessageLocator {
+        chunk_pos,
+        offset: 0,
+        conn_id: 0,
+        time: 10,
+    }];
+    assert_eq!(bag.index_entries().unwrap(), expected);
+    assert_eq!(bag.message_locators_in_range(0, 20).unwrap(), expected);
+    let data = bag.nth_message("/chatter", 0, |m| m.data.to_vec()).unwrap();
+    assert_eq!(data.unwrap(), b"a");
+}
+
+#[test]
+fn read_record_header() {
+    let path = common::bag("read_record_header", &common::chunk(b""), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let start = common::chunk_section_start(0, 1);
+
+    let fields = bag.read_record_header(start).unwrap();
+    let names: Vec<_> = fields.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, ["op", "compression", "size"]);
+    assert_eq!(fields[0].1, [0x05]);
+    assert!(matches!(bag.read_record_header(0), Err(Error::OutOfBounds)));
+}
+
+/// Bag with connection `conn` stored in the index section and in every
+/// chunk, with chunks built from `(time, data)` pairs of messages.
+fn diff_bag(name: &str, conn: Vec<u8>, chunks: &[&[(u64, &[u8])]]) -> RosBag {
+    let mut pos = common::chunk_section_start(1, chunks.len() as u32);
+    let mut data = Vec::new();
+    let mut index = conn.clone();
+    for msgs in chunks {
+        let mut chunk = conn.clone();
+        for &(t, payload) in msgs.iter() {
+            chunk.extend(common::message(0, t, payload));
+        }
+        let chunk = common::chunk(&chunk);
+        let start = msgs.iter().map(|m| m.0).min().unwrap();
+        let end = msgs.iter().map(|m| m.0).max().unwrap();
+        index.extend(common::chunk_info(
+            pos,
+            start,
+            end,
+            &[(0, msgs.len() as u32)],
+        ));
+        pos += chunk.len() as u64;
+        data.extend(chunk);
+    }
+    RosBag::new(common::bag(name, &data, &index, 1, chunks.len() as u32)).unwrap()
+}
+
+#[test]
+fn diff() {
+    let conn = || common::connection(0, "/chatter");
+    let a = diff_bag("diff_a", conn(), &[&[(10, b"a"), (20, b"b"), (30, b"c")]]);
+    let b = diff_bag("diff_b", conn(), &[&[(10, b"a"), (20, b"x"), (40, b"d")]]);
+
+    assert!(a.diff(&a).unwrap().is_empty());
+    let diff = a.diff(&b).unwrap();
+    let times = |v: &[rosbag::MessageDiff]| v.iter().map(|m| m.time).collect::<Vec<_>>();
+    assert_eq!(times(&diff.differing), [20]);
+    assert_eq!(times(&diff.missing), [30]);
+    assert_eq!(times(&diff.extra), [40]);
+    assert!(diff.missing_topics.is_empty() && diff.type_mismatches.is_empty());
+}
+
+#[test]
+fn diff_duplicate_timestamps() {
+    let conn = |data_topic: &str| {
+        let fields = [
+            ("topic", data_topic.as_bytes()),
+            ("type", &b"std_msgs/String"[..]),
+            ("md5sum", b"992ce8a1687cec8c8bd883ec73ca41d1"),
+            ("message_definition", b""),
+        ];
+        common::connection_with_fields(0, "/chatter", &fields)
+    };
+    // overlapping chunks with messages out of order
+    let a = diff_bag(
+        "diff_dup_a",
+        conn("/chatter"),
+        &[
+            &[(20, b"c"), (10, b"a")],
+            &[(10, b"b"), (20, b"d"), (30, b"e")],
+        ],
+    );
+    let b = diff_bag(
+        "diff_dup_b",
+        conn("/remapped"),
+        &[
+            &[(10, b"b"), (30, b"e")],
+            &[(10, b"a"), (20, b"d"), (20, b"x"), (30, b"e")],
+        ],
+    );
+
+    let diff = a.diff(&b).unwrap();
+    let times = |v: &[rosbag::MessageDiff]| v.iter().map(|m| m.time).collect::<Vec<_>>();
+    assert_eq!(times(&diff.differing), [20]);
+    assert!(diff.missing.is_empty());
+    assert_eq!(times(&diff.extra), [30]);
+    assert_eq!(diff.extra[0].topic, "/chatter");
+    assert!(diff.missing_topics.is_empty() && diff.extra_topics.is_empty());
+}
+
+#[test]
+fn is_topic_latched() {
+    let data = common::header(&[
+        ("topic", b"/tf_static"),
+        ("type", b"std_msgs/String"),
+        ("md5sum", b"992ce8a1687cec8c8bd883ec73ca41d1"),
+        ("message_definition", b"string data\n"),
+        ("latching", b"1"),
+    ]);
+    let latched = common::record(
+        &[
+            ("op", &[0x07]),
+            ("conn", &1u32.to_le_bytes()),
+            ("topic", b"/tf_static"),
+        ],
+        &data,
+    );
+    let index = [common::connection(0, "/chatter"), latched].concat();
+    let path = common::bag("latched", &[], &index, 2, 0);
+    let bag = RosBag::new(&path).unwrap();
+
+    assert_eq!(bag.is_topic_latched("/tf_static").unwrap(), Some(true));
+    assert_eq!(bag.is_topic_latched("/chatter").unwrap(), Some(false));
+    assert_eq!(bag.is_topic_latched("/missing").unwrap(), None);
+}
+
+#[test]
+fn decimator() {
+    let msgs: Vec<_> = [
+        (0, 0),
+        (1, 0),
+        (0, 40),
+        (0, 100),
+        (1, 90),
+        (0, 150),
+        (0, 210),
+    ]
+    .iter()
+    .map(|&(conn, ms)| common::message(conn, ms * 1_000_000, b""))
+    .collect();
+    let path = common::bag("decimate", &common::chunk(&msgs.concat()), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+
+    let mut decimator = rosbag::Decimator::new(10.0);
+    let kept: Vec<_> = chunk
+        .messages()
+        .filter_map(|msg| match msg.unwrap() {
+            MessageRecord::MessageData(msg) if decimator.keep(&msg) => {
+                Some((msg.conn_id, msg.time / 1_000_000))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(kept, [(0, 0), (1, 0), (0, 100), (0, 210)]);
+}
+
+#[test]
+fn time_range_fast() {
+    let path = common::bag("time_range_fast_empty", &[], &[], 0, 0);
+    assert_eq!(
+        RosBag::new(&path).unwrap().time_range_fast(false).unwrap(),
+        None
+    );
+
+    let chunks = [
+        common::chunk(&common::message(0, 10, b"a")),
+        common::chunk(&common::message(0, 20, b"b")),
+    ]
+    .concat();
+    let start = common::chunk_section_start(1, 2);
+    let second = start + common::chunk(&common::message(0, 10, b"a")).len() as u64;
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 10, &[(0, 1)]),
+        common::chunk_info(second, 5, 20, &[(0, 1)]),
+    ]
+    .concat();
+    let path = common::bag("time_range_fast", &chunks, &index, 1, 2);
+    let bag = RosBag::new(&path).unwrap();
+
+    assert_eq!(bag.time_range_fast(false).unwrap(), Some((10, 20)));
+    assert_eq!(bag.time_range_fast(true).unwrap(), Some((5, 20)));
+
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let path = common::bag("time_range_fast_one", &chunks, &index, 1, 1);
+    let bag = RosBag::new(&path).unwrap();
+    assert_eq!(bag.time_range_fast(false).unwrap(), Some((10, 10)));
+
+    // connection count in the bag header does not match the index section
+    let path = common::bag("time_range_fast_conns", &chunks, &index, 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    assert!(matches!(
+        bag.time_range_fast(false),
+        Err(Error::UnexpectedIndexSectionRecord("Connection"))
+    ));
+}
+
+#[test]
+fn chunk_connections() {
+    let data = [
+        common::connection(0, "/first"),
+        common::message(0, 10, b"a"),
+        common::connection(1, "/second"),
+        common::message(1, 20, b"b"),
+    ]
+    .concat();
+    let path = common::bag("chunk_connections", &common::chunk(&data), &[], 0, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+
+    let topics: Vec<_> = chunk.connections().map(|c| c.unwrap().topic).collect();
+    assert_eq!(topics, ["/first", "/second"]);
+}
+
+#[test]
+fn unknown_field_policy() {
+    use rosbag::UnknownFieldPolicy;
+
+    let msg = common::record(
+        &[
+            ("op", &[0x02]),
+            ("conn", &0u32.to_le_bytes()),
+            ("time", &common::time(10)),
+            ("vendor", b"x"),
+        ],
+        b"a",
+    );
+    let path = common::bag("unknown_field", &common::chunk(&msg), &[], 0, 1);
+    let count = |policy| {
+        let bag = OpenOptions::new()
+            .unknown_field_policy(policy)
+            .open(&path)
+            .unwrap();
+        let chunk = match bag.chunk_records().next().unwrap().unwrap() {
+            ChunkRecord::Chunk(chunk) => chunk,
+            _ => panic!("expected chunk"),
+        };
+        let res = chunk.messages().collect::<Result<Vec<_>, _>>();
+        res.map(|msgs| msgs.len())
+    };
+
+    assert_eq!(count(UnknownFieldPolicy::Ignore).unwrap(), 1);
+    assert_eq!(count(UnknownFieldPolicy::Warn).unwrap(), 1);
+    assert!(matches!(
+        count(UnknownFieldPolicy::Error),
+        Err(Error::UnknownField(name)) if name == "vendor"
+    ));
+}
+
+#[test]
+fn nth_message() {
+    let m0 = common::message(0, 10, b"a");
+    let m1 = common::message(1, 15, b"x");
+    let m2 = common::message(0, 20, b"b");
+    let chunk1 = common::chunk(&[m0.clone(), m1].concat());
+    let chunks = [
+        chunk1.clone(),
+        common::index_data(0, &[(10, 0)]),
+        common::index_data(1, &[(15, m0.len() as u32)]),
+        common::chunk(&[m2.clone(), common::message(0, 30, b"c")].concat()),
+        common::index_data(0, &[(20, 0), (30, m2.len() as u32)]),
+    ]
+    .concat();
+    let start = common::chunk_section_start(2, 2);
+    let second = start + (chunk1.len() + common::index_data(0, &[(10, 0)]).len() * 2) as u64;
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/other"),
+        common::chunk_info(start, 10, 15, &[(0, 1), (1, 1)]),
+        common::chunk_info(second, 20, 30, &[(0, 2)]),
+    ]
+    .concat();
+    let path = common::bag("nth_message", &chunks, &index, 2, 2);
+    let bag = RosBag::new(&path).unwrap();
+
+    let data = |n| bag.nth_message("/chatter", n, |m| m.data.to_vec()).unwrap();
+    assert_eq!(data(0).unwrap(), b"a");
+    assert_eq!(data(1).unwrap(), b"b");
+    assert_eq!(data(2).unwrap(), b"c");
+    assert_eq!(data(3), None);
+    assert_eq!(bag.nth_message("/other", 0, |m| m.time).unwrap(), Some(15));
+    assert_eq!(bag.nth_message("/missing", 0, |m| m.time).unwrap(), None);
+}
+
+fn time_windows(bag: &RosBag, tolerance: u64) -> (Vec<(u64, Vec<u64>)>, u64) {
+    let mut windows = Vec::new();
+    let late = bag
+        .for_each_time_window(10, tolerance, |start, msgs| {
+            windows.push((start, msgs.iter().map(|m| m.time).collect()));
+        })
+        .unwrap();
+    (windows, late)
+}
+
+#[test]
+fn time_windows_span_chunks() {
+    let chunk = |times: &[u64]| {
+        let msgs: Vec<_> = times.iter().map(|&t| common::message(0, t, b"")).collect();
+        common::chunk(&msgs.concat())
+    };
+    let chunks = [chunk(&[1, 5, 12]), chunk(&[14, 25])].concat();
+    let bag = RosBag::new(common::bag("windows_chunks", &chunks, &[], 0, 2)).unwrap();
+
+    let (windows, late) = time_windows(&bag, 0);
+    assert_eq!(
+        windows,
+        [(0, vec![1, 5]), (10, vec![12, 14]), (20, vec![25])]
+    );
+    assert_eq!(late, 0);
+}
+
+#[test]
+fn time_windows_out_of_order() {
+    let chunk = |times: &[u64]| {
+        let msgs: Vec<_> = times.iter().map(|&t| common::message(0, t, b"")).collect();
+        common::chunk(&msgs.concat())
+    };
+    let chunks = [chunk(&[1, 12]), chunk(&[3, 16])].concat();
+    let bag = RosBag::new(common::bag("windows_order", &chunks, &[], 0, 2)).unwrap();
+
+    let (windows, late) = time_windows(&bag, 0);
+    assert_eq!(windows, [(0, vec![1]), (10, vec![12, 16])]);
+    assert_eq!(late, 1);
+
+    let (windows, late) = time_windows(&bag, 5);
+    assert_eq!(windows, [(0, vec![1, 3]), (10, vec![12, 16])]);
+    assert_eq!(late, 0);
+}
+
+fn open_error(buf: &[u8]) -> Error {
+    let err = common::open(buf).err().unwrap();
+    *err.into_inner().unwrap().downcast::<Error>().unwrap()
+}
+
+#[test]
+fn unsupported_bag_version() {
+    let mut buf = common::bag_bytes(&[], &[], 0, 0);
+    buf[..common::VERSION.len()].copy_from_slice(b"#ROSBAG V1.2\n");
+    assert!(matches!(open_error(&buf), Error::UnsupportedBagVersion(v) if v == "1.2"));
+
+    buf[..common::VERSION.len()].copy_from_slice(b"#NOTABAG V12\n");
+    assert!(matches!(open_error(&buf), Error::InvalidHeader));
+}
+
+#[test]
+fn topic_schema() {
+    let index = [common::connection(0, "/chatter")].concat();
+    let bag = RosBag::new(common::bag("topic-schema", &[], &index, 1, 0)).unwrap();
+    let (def, md5) = bag.topic_schema("/chatter").unwrap().unwrap();
+    assert_eq!(def, "string data\n");
+    assert_eq!(md5[..2], [0x99, 0x2c]);
+    assert_eq!(md5[15], 0xd1);
+    assert_eq!(bag.topic_schema("/imu").unwrap(), None);
+
+    let fields = common::header(&[("op", &[0x02]), ("vendor", b"a=b")]);
+    let fields: Vec<_> = FieldIterator::new(&fields)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(fields, [("op", &[0x02][..]), ("vendor", &b"a=b"[..])]);
+    let mut iter = FieldIterator::new(&[4, 0, 0, 0, b'o', b'p']);
+    assert!(iter.next().unwrap().is_err());
+}
+
+#[test]
+fn as_typed_slice() {
+    let payload = [1u32.to_le_bytes(), 2u32.to_le_bytes()].concat();
+    let mut chunk = Vec::new();
+    // padding messages shift payloads to every possible alignment
+    for pad in 0..4 {
+        chunk.extend(common::message(1, 0, &vec![0; pad]));
+        chunk.extend(common::message(0, 10, &payload));
+    }
+    let bag = RosBag::new(common::bag(
+        "typed-slice",
+        &common::chunk(&chunk),
+        &[],
+        0,
+        1,
+    ))
+    .unwrap();
+    let chunk = common::first_chunk(&bag);
+    let (mut borrowed, mut owned) = (0, 0);
+    for msg in chunk.messages() {
+        let msg = match msg.unwrap() {
+            MessageRecord::MessageData(msg) if msg.conn_id == 0 => msg,
+            _ => continue,
+        };
+        let vals = msg.as_u32_slice().unwrap();
+        assert_eq!(*vals, [1, 2]);
+        let aligned = msg.data.as_ptr() as usize % 4 == 0;
+        match vals {
+            Cow::Borrowed(_) if aligned => borrowed += 1,
+            Cow::Owned(_) if !aligned => owned += 1,
+            _ => panic!("unexpected slice kind"),
+        }
+        assert_eq!(*msg.as_u64_slice().unwrap(), [1 | 2 << 32]);
+        assert_eq!(msg.as_f64_slice().unwrap().len(), 1);
+        assert_eq!(msg.as_i16_slice().unwrap().len(), 4);
+    }
+    assert!(borrowed > 0 && owned > 0);
+
+    let bag = RosBag::new(common::bag(
+        "typed-slice-len",
+        &common::chunk(&common::message(0, 10, &[0; 6])),
+        &[],
+        0,
+        1,
+    ))
+    .unwrap();
+    let chunk = common::first_chunk(&bag);
+    match chunk.messages().next().unwrap().unwrap() {
+        MessageRecord::MessageData(msg) => {
+            assert!(msg.as_u32_slice().is_none());
+            assert_eq!(msg.as_u16_slice().unwrap().len(), 3);
+        }
+        _ => panic!("expected message"),
+    }
+}
+
+#[test]
+fn for_each_connection() {
+    let chunks = [
+        common::chunk(
+            &[
+                common::connection(0, "/chatter"),
+                common::message(0, 10, b"a"),
+            ]
+            .concat(),
+        ),
+        common::chunk(&common::connection(1, "/imu")),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("for-each-conn", &chunks, &index, 2, 2)).unwrap();
+    let mut conns = Vec::new();
+    bag.for_each_connection(|conn| conns.push((conn.id, conn.topic.to_string())))
+        .unwrap();
+    let expected = [(0, "/chatter"), (1, "/imu"), (0, "/chatter"), (1, "/imu")];
+    let expected: Vec<_> = expected.iter().map(|&(i, t)| (i, t.to_string())).collect();
+    assert_eq!(conns, expected);
+}
+
+#[test]
+fn prefetch_index() {
+    let index = [common::connection(0, "/chatter")].concat();
+    let path = common::bag("prefetch", &[], &index, 1, 0);
+    let bag = OpenOptions::new().prefetch_index(true).open(&path).unwrap();
+    assert_eq!(bag.index_connections().unwrap().len(), 1);
+}
+
+#[test]
+fn max_record_size() {
+    let msg = common::message(0, 10, &[0; 100]);
+    let path = common::bag("max-record-size", &common::chunk(&msg), &[], 0, 1);
+
+    let bag = OpenOptions::new()
+        .max_record_size(msg.len() as u32)
+        .open(&path)
+        .unwrap();
+    assert_eq!(common::first_chunk(&bag).messages().count(), 1);
+
+    let bag = OpenOptions::new()
+        .max_record_size(msg.len() as u32 - 1)
+        .open(&path)
+        .unwrap();
+    let limit = msg.len() as u32 - 1;
+    assert!(matches!(
+        bag.chunk_records().next(),
+        Some(Err(Error::RecordTooLarge { declared, limit: l })) if declared == limit + 1 && l == limit
+    ));
+}
+
+/// Serialized `std_msgs/Header` followed by `rest` of the message.
+fn std_header(seq: u32, stamp: u64, frame_id: &str, rest: &[u8]) -> Vec<u8> {
+    let mut buf = seq.to_le_bytes().to_vec();
+    buf.extend_from_slice(&common::time(stamp));
+    buf.extend_from_slice(&(frame_id.len() as u32).to_le_bytes());
+    buf.extend_from_slice(frame_id.as_bytes());
+    buf.extend_from_slice(rest);
+    buf
+}
+
+#[test]
+fn peek_header() {
+    let defs = [
+        "Header header\nstring data\n",
+        "# comment\nuint8 MODE=1 # constant\n  std_msgs/Header header\n",
+        "string data\nHeader header\n",
+    ];
+    let mut chunk = Vec::new();
+    for (id, def) in defs.iter().enumerate() {
+        chunk.extend(common::connection_with_definition(id as u32, "/t", def));
+        chunk.extend(common::message(
+            id as u32,
+            10,
+            &std_header(7, 5, "map", b"x"),
+        ));
+    }
+    let bag = RosBag::new(common::bag(
+        "peek-header",
+        &common::chunk(&chunk),
+        &[],
+        0,
+        1,
+    ))
+    .unwrap();
+    let chunk = common::first_chunk(&bag);
+    let mut headers = Vec::new();
+    for (conn, msg) in chunk.messages_resolved().map(Result::unwrap) {
+        let header = msg.peek_header(&conn.unwrap()).unwrap();
+        headers.push(header.map(|h| (h.seq, h.stamp, h.frame_id)));
+    }
+    assert_eq!(headers, [Some((7, 5, "map")), Some((7, 5, "map")), None]);
+}
+
+#[test]
+fn for_each_message_with_stamp() {
+    let chunk = [
+        common::connection_with_definition(1, "/odom", "Header header\n"),
+        common::message(0, 10, b"a"),
+        common::message(1, 20, &std_header(0, 15, "", b"")),
+        common::message(2, 30, b"b"),
+    ]
+    .concat();
+    let index = [common::connection(0, "/chatter")].concat();
+    let path = common::bag("with-stamp", &common::chunk(&chunk), &index, 1, 1);
+    let bag = RosBag::new(&path).unwrap();
+    let mut stamps = Vec::new();
+    let res = bag.for_each_message_with_stamp(|msg, stamp| stamps.push((msg.time, stamp)));
+    // connection 2 is not stored anywhere
+    assert!(matches!(res, Err(Error::InvalidRecord)));
+    assert_eq!(stamps, [(10, None), (20, Some(15))]);
+}
+
+#[test]
+fn chunk_records_range() {
+    let start = common::chunk_section_start(0, 3);
+    let chunks = [
+        common::chunk(&common::message(0, 10, b"a")),
+        common::index_data(0, &[(10, 0)]),
+        common::chunk(&common::message(0, 20, b"bb")),
+        common::chunk(&common::message(0, 30, b"ccc")),
+        common::index_data(0, &[(20, 0), (30, 0)]),
+    ];
+    let data = chunks.concat();
+    let end = start + data.len() as u64;
+    let bag = RosBag::new(common::bag("records-range", &data, &[], 0, 3)).unwrap();
+    let count = |a, b| {
+        bag.chunk_records_range(a, b)
+            .unwrap()
+            .map(Result::unwrap)
+            .count()
+    };
+
+    let mut pos = start;
+    for record in &chunks {
+        assert_eq!(count(pos, pos + 1), 1);
+        assert_eq!(count(pos + 1, pos + record.len() as u64), 0);
+        pos += record.len() as u64;
+    }
+    assert_eq!(count(start, end), chunks.len());
+    // every record is read exactly once for any split position
+    for split in start..=end {
+        assert_eq!(count(start, split) + count(split, end), chunks.len());
+        for split2 in (split..=end).step_by(7) {
+            let n = count(start, split) + count(split, split2) + count(split2, end);
+            assert_eq!(n, chunks.len());
+        }
+    }
+    assert!(bag.chunk_records_range(end, end + 1).is_err());
+    assert!(bag.chunk_records_range(start + 1, start).is_err());
+}
+
+#[test]
+fn clone_bag() {
+    let chunks = common::chunk(&common::message(0, 10, b"a"));
+    let index = [common::connection(0, "/chatter")].concat();
+    let bag = RosBag::new(common::bag("clone", &chunks, &index, 1, 1)).unwrap();
+    let cloned = bag.clone();
+    drop(bag);
+
+    assert_eq!(cloned.chunk_records().count(), 1);
+    assert_eq!(cloned.index_connections().unwrap()[0].topic, "/chatter");
+    let handle = std::thread::spawn(move || cloned.take_messages(1, |_| {}).unwrap());
+    assert_eq!(handle.join().unwrap(), 1);
+}
+
+#[test]
+fn skip_unknown() {
+    let unknown = common::record(&[("op", &[0x42])], b"vendor");
+    let start = common::chunk_section_start(1, 1);
+    let chunk = common::chunk(&[unknown.clone(), common::message(0, 10, b"a")].concat());
+    let chunks = [unknown.clone(), chunk].concat();
+    let index = [
+        unknown.clone(),
+        common::connection(0, "/chatter"),
+        common::chunk_info(start + unknown.len() as u64, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("skip-unknown", &chunks, &index, 1, 1)).unwrap();
+
+    assert!(matches!(
+        bag.chunk_records().next(),
+        Some(Err(Error::UnknownRecord(0x42)))
+    ));
+    assert!(matches!(
+        bag.index_records().next(),
+        Some(Err(Error::UnknownRecord(0x42)))
+    ));
+    let records: Vec<_> = bag.chunk_records().skip_unknown(true).collect();
+    assert_eq!(records.len(), 1);
+    let chunk = match records.into_iter().next().unwrap().unwrap() {
+        ChunkRecord::Chunk(chunk) => chunk,
+        _ => panic!("expected chunk"),
+    };
+    let mut msgs = chunk.messages();
+    assert!(matches!(msgs.next(), Some(Err(Error::UnknownRecord(0x42)))));
+    assert!(msgs.next().is_none());
+    let msgs: Vec<_> = chunk.messages().skip_unknown(true).collect();
+    assert!(matches!(msgs[..], [Ok(MessageRecord::MessageData(_))]));
+    let records: Vec<_> = bag.index_records().skip_unknown(true).collect();
+    assert!(matches!(
+        records[..],
+        [
+            Ok(IndexRecord::Connection(_)),
+            Ok(IndexRecord::ChunkInfo(_))
+        ]
+    ));
+}
+
+#[test]
+fn demux() {
+    let chunk = [
+        common::message(0, 10, b"a"),
+        common::connection(2, "/local"),
+        common::message(1, 20, b"b"),
+        common::message(2, 30, b"c"),
+        common::message(0, 40, b"d"),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::connection(1, "/imu"),
+    ]
+    .concat();
+    let path = common::bag("demux", &common::chunk(&chunk), &index, 2, 1);
+    let bag = RosBag::new(&path).unwrap();
+
+    let msgs = std::cell::RefCell::new(Vec::new());
+    let mut topics = Vec::new();
+    bag.demux(|conn| {
+        topics.push(conn.topic.to_string());
+        let topic = conn.topic.to_string();
+        let msgs = &msgs;
+        if topic == "/imu" {
+            return None;
+        }
+        Some(move |msg: rosbag::record_types::MessageData<'_>| {
+            msgs.borrow_mut().push((topic.clone(), msg.time))
+        })
+    })
+    .unwrap();
+    assert_eq!(topics, ["/chatter", "/imu", "/local"]);
+    let expected = [("/chatter", 10), ("/local", 30), ("/chatter", 40)];
+    let expected: Vec<_> = expected.iter().map(|&(t, n)| (t.to_string(), n)).collect();
+    assert_eq!(msgs.into_inner(), expected);
+
+    let chunk = common::chunk(&common::message(3, 10, b"a"));
+    let bag = RosBag::new(common::bag("demux-orphan", &chunk, &index, 2, 1)).unwrap();
+    let res = bag.demux(|_| Some(|_: rosbag::record_types::MessageData<'_>| {}));
+    assert!(matches!(res, Err(Error::InvalidRecord)));
+}
+
+#[test]
+fn index_fingerprint() {
+    let start = common::chunk_section_start(1, 1);
+    let build = |name, payload: &[u8], end_time| {
+        let chunks = common::chunk(&common::message(0, 10, payload));
+        let index = [
+            common::connection(0, "/chatter"),
+            common::chunk_info(start, 10, end_time, &[(0, 1)]),
+        ]
+        .concat();
+        let bag = RosBag::new(common::bag(name, &chunks, &index, 1, 1)).unwrap();
+        bag.index_fingerprint().unwrap()
+    };
+    let fingerprint = build("fingerprint", b"a", 10);
+    assert_eq!(build("fingerprint-same", b"a", 10), fingerprint);
+    // chunk data is not read
+    assert_eq!(build("fingerprint-payload", b"b", 10), fingerprint);
+    assert_ne!(build("fingerprint-time", b"a", 11), fingerprint);
+    assert_ne!(build("fingerprint-len", b"ab", 10), fingerprint);
+}
+
+#[test]
+fn decode_record() {
+    let buf = common::message(3, 10, b"a");
+    match rosbag::record_types::decode_record(&buf).unwrap() {
+        Record::MessageData(msg) => {
+            assert_eq!((msg.conn_id, msg.time, msg.data), (3, 10, &b"a"[..]))
+        }
+        _ => panic!("expected message"),
+    }
+    let buf = common::connection(1, "/chatter");
+    let record = rosbag::record_types::decode_record(&buf).unwrap();
+    assert_eq!(record.kind(), "Connection");
+
+    let mut trailing = buf.clone();
+    trailing.push(0);
+    assert!(matches!(
+        rosbag::record_types::decode_record(&trailing),
+        Err(Error::InvalidRecord)
+    ));
+    assert!(rosbag::record_types::decode_record(&buf[..buf.len() - 1]).is_err());
+}
+
+#[test]
+fn record_conversions() {
+    let start = common::chunk_section_start(1, 1);
+    let chunk = [
+        common::connection(0, "/chatter"),
+        common::message(0, 10, b"a"),
+    ]
+    .concat();
+    let chunks = [common::chunk(&chunk), common::index_data(0, &[(10, 0)])].concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("record-kind", &chunks, &index, 1, 1)).unwrap();
+
+    let kinds: Vec<_> = bag
+        .chunk_records()
+        .map(|r| Record::from(r.unwrap()).kind())
+        .collect();
+    assert_eq!(kinds, ["Chunk", "IndexData"]);
+    let kinds: Vec<_> = bag
+        .index_records()
+        .map(|r| Record::from(r.unwrap()).kind())
+        .collect();
+    assert_eq!(kinds, ["Connection", "ChunkInfo"]);
+    let chunk = common::first_chunk(&bag);
+    let kinds: Vec<_> = chunk
+        .messages()
+        .map(|r| Record::from(r.unwrap()).kind())
+        .collect();
+    assert_eq!(kinds, ["Connection", "MessageData"]);
+    match Record::from(bag.chunk_records().next().unwrap().unwrap()) {
+        Record::Chunk(chunk) => assert_eq!(chunk.messages().count(), 2),
+        _ => panic!("expected chunk"),
+    }
+}
+
+#[test]
+fn is_remapped() {
+    let fields = [
+        ("topic", &b"/chatter"[..]),
+        ("type", b"std_msgs/String"),
+        ("md5sum", b"992ce8a1687cec8c8bd883ec73ca41d1"),
+        ("message_definition", b"string data\n"),
+    ];
+    let remapped = common::connection_with_fields(1, "/robot/chatter", &fields);
+    let index = [common::connection(0, "/chatter"), remapped].concat();
+    let bag = RosBag::new(common::bag("remapped", &[], &index, 2, 0)).unwrap();
+    let conns = bag.index_connections().unwrap();
+    assert!(!conns[0].is_remapped());
+    assert!(conns[1].is_remapped());
+    assert_eq!(conns[1].topic, "/chatter");
+    assert_eq!(conns[1].storage_topic, "/robot/chatter");
+}
+
+#[test]
+fn compression_stats() {
+    let msg = common::message(0, 10, b"a");
+    let packed = common::record(
+        &[
+            ("op", &[0x05]),
+            ("compression", b"rev"),
+            ("size", &(4 * msg.len() as u32).to_le_bytes()),
+        ],
+        &msg,
+    );
+    let chunks = [
+        common::chunk(&msg),
+        common::index_data(0, &[(10, 0)]),
+        packed,
+        common::chunk(&msg),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("compression-stats", &chunks, &[], 0, 3)).unwrap();
+    let stats = bag.compression_stats().unwrap();
+    assert_eq!(stats.len(), 2);
+    let none = &stats[&Compression::None];
+    assert_eq!(none.chunks, 2);
+    assert_eq!(none.compressed_bytes, 2 * msg.len() as u64);
+    assert_eq!(none.uncompressed_bytes, 2 * msg.len() as u64);
+    assert_eq!(none.ratio(), 1.0);
+    let custom = &stats[&Compression::Custom];
+    assert_eq!(custom.chunks, 1);
+    assert_eq!(custom.ratio(), 4.0);
+    assert_eq!(bag.compression_ratio().unwrap(), 2.0);
+    assert_eq!(
+        bag.total_uncompressed_bytes().unwrap(),
+        6 * msg.len() as u64
+    );
+
+    let bag = RosBag::new(common::bag("compression-stats-empty", &[], &[], 0, 0)).unwrap();
+    assert!(bag.compression_stats().unwrap().is_empty());
+    assert_eq!(bag.compression_ratio().unwrap(), 1.0);
+}
+
+#[test]
+fn resume_after_end() {
+    let start = common::chunk_section_start(1, 1);
+    let chunk = [
+        common::connection(0, "/chatter"),
+        common::message(0, 10, b"a"),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 10, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("resume", &common::chunk(&chunk), &index, 1, 1)).unwrap();
+
+    let mut records = bag.chunk_records();
+    assert_eq!(records.by_ref().count(), 1);
+    assert!(records.next().is_none());
+    records.seek(start).unwrap();
+    assert!(matches!(records.next(), Some(Ok(ChunkRecord::Chunk(_)))));
+
+    let mut records = bag.index_records();
+    let index_pos = records.position();
+    assert_eq!(records.by_ref().count(), 2);
+    records.seek(index_pos).unwrap();
+    assert!(matches!(
+        records.next(),
+        Some(Ok(IndexRecord::Connection(_)))
+    ));
+
+    let chunk = common::first_chunk(&bag);
+    let mut msgs = chunk.messages();
+    assert_eq!(msgs.by_ref().count(), 2);
+    msgs.seek(0).unwrap();
+    assert!(matches!(
+        msgs.next(),
+        Some(Ok(MessageRecord::Connection(_)))
+    ));
+}
+
+#[test]
+fn raw_records() {
+    let start = common::chunk_section_start(1, 1);
+    let records = [
+        common::chunk(&common::message(0, 10, b"a")),
+        common::index_data(0, &[(10, 0)]),
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 10, 10, &[(0, 1)]),
+    ];
+    let chunks = records[..2].concat();
+    let index = records[2..].concat();
+    let path = common::bag("raw-records", &chunks, &index, 1, 1);
+    let bag = RosBag::new(&path).unwrap();
+
+    let raw: Vec<_> = bag.raw_records().collect::<Result<_, _>>().unwrap();
+    assert_eq!(raw.len(), records.len());
+    let mut pos = start;
+    for ((raw_pos, buf), record) in raw.iter().zip(&records) {
+        assert_eq!((*raw_pos, *buf), (pos, &record[..]));
+        pos += record.len() as u64;
+    }
+
+    let mut buf = std::fs::read(&path).unwrap();
+    buf.truncate(buf.len() - 1);
+    let bag = common::open(&buf).unwrap();
+    let raw: Vec<_> = bag.raw_records().collect();
+    assert_eq!(raw.len(), records.len());
+    assert!(raw[..3].iter().all(Result::is_ok));
+    assert!(raw[3].is_err());
+}
+
+#[test]
+fn display_records() {
+    let start = common::chunk_section_start(1, 1);
+    let chunks = [
+        common::chunk(&common::message(0, 1_000_000_005, b"abc")),
+        common::index_data(0, &[(1_000_000_005, 0)]),
+    ]
+    .concat();
+    let index = [
+        common::connection(0, "/chatter"),
+        common::chunk_info(start, 1_000_000_005, 2_500_000_000, &[(0, 1)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("display", &chunks, &index, 1, 1)).unwrap();
+
+    let records: Vec<_> = bag.index_records().map(Result::unwrap).collect();
+    match &records[..] {
+        [IndexRecord::Connection(conn), IndexRecord::ChunkInfo(info)] => {
+            assert_eq!(conn.to_string(), "/chatter (std_msgs/String, id=0)");
+            assert_eq!(
+                info.to_string(),
+                format!("chunk_pos={} [1.000000005, 2.500000000] entries=1", start)
+            );
+        }
+        _ => panic!("unexpected index records"),
+    }
+    match bag.chunk_records().nth(1).unwrap().unwrap() {
+        ChunkRecord::IndexData(data) => assert_eq!(data.to_string(), "conn=0 entries=1"),
+        _ => panic!("expected index data"),
+    }
+    let chunk = common::first_chunk(&bag);
+    match chunk.messages().next().unwrap().unwrap() {
+        MessageRecord::MessageData(msg) => {
+            assert_eq!(msg.to_string(), "conn=0 t=1.000000005 len=3")
+        }
+        _ => panic!("expected message"),
+    }
+}
+
+#[test]
+fn scan_integrity() {
+    let start = common::chunk_section_start(0, 2);
+    let records = [
+        common::chunk(&common::message(0, 10, b"a")),
+        // message without `conn` and `time` fields
+        common::chunk(&common::record(&[("op", &[0x02])], b"b")),
+        common::record(&[("op", &[0x04])], b""),
+    ];
+    let mut buf = common::bag_bytes(&records.concat(), &[], 0, 2);
+    // truncated record at the end of the file
+    buf.extend_from_slice(&100u32.to_le_bytes());
+    let bag = common::open(&buf).unwrap();
+
+    let events: Vec<_> = bag.scan_integrity().collect();
+    let pos1 = start + records[0].len() as u64;
+    let pos2 = pos1 + records[1].len() as u64;
+    let pos3 = pos2 + records[2].len() as u64;
+    let positions: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            IntegrityEvent::ChunkOk { pos } => Some(("ok", *pos)),
+            IntegrityEvent::ChunkError { pos, .. } => Some(("chunk", *pos)),
+            IntegrityEvent::RecordError { pos, .. } => Some(("record", *pos)),
+            IntegrityEvent::Summary { .. } => None,
+        })
+        .collect();
+    let expected = [
+        ("ok", start),
+        ("chunk", pos1),
+        ("record", pos2),
+        ("record", pos3),
+    ];
+    assert_eq!(positions, expected);
+    assert!(matches!(
+        events[3],
+        IntegrityEvent::RecordError {
+            error: Error::ImplausibleLength { .. },
+            ..
+        }
+    ));
+    assert!(matches!(
+        events[4],
+        IntegrityEvent::Summary {
+            chunks_ok: 1,
+            chunks_failed: 1,
+            records_failed: 2,
+        }
+    ));
+
+    let bag = RosBag::new(common::bag("integrity-empty", &[], &[], 0, 0)).unwrap();
+    let events: Vec<_> = bag.scan_integrity().collect();
+    assert!(matches!(
+        events[..],
+        [IntegrityEvent::Summary {
+            chunks_ok: 0,
+            chunks_failed: 0,
+            records_failed: 0,
+        }]
+    ));
+}
+
+#[test]
+fn record_data_length() {
+    let mut info = common::chunk_info(0, 10, 10, &[(0, 1)]);
+    // declare 2 entries while storing only one
+    let pos = info.windows(5).position(|w| w == b"count").unwrap() + 6;
+    info[pos..pos + 4].copy_from_slice(&2u32.to_le_bytes());
+    let index = [common::connection(0, "/chatter"), info].concat();
+    let bag = RosBag::new(common::bag("data-len-info", &[], &index, 1, 1)).unwrap();
+    let records: Vec<_> = bag.index_records().collect();
+    assert!(matches!(
+        records[..],
+        [Ok(IndexRecord::Connection(_)), Err(Error::InvalidRecord)]
+    ));
+
+    // 13 bytes of data can not hold whole entries
+    let data = [common::time(10).to_vec(), vec![0; 5]].concat();
+    let index_data = common::record(
+        &[
+            ("op", &[0x04]),
+            ("ver", &1u32.to_le_bytes()),
+            ("conn", &0u32.to_le_bytes()),
+            ("count", &1u32.to_le_bytes()),
+        ],
+        &data,
+    );
+    let bag = RosBag::new(common::bag("data-len-index", &index_data, &[], 0, 0)).unwrap();
+    assert!(matches!(
+        bag.chunk_records().next(),
+        Some(Err(Error::InvalidRecord))
+    ));
+    let index_data = [
+        common::index_data(0, &[(10, 0)]),
+        common::index_data(1, &[(20, 0)]),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("data-len-ok", &index_data, &[], 0, 0)).unwrap();
+    let conns: Vec<_> = bag
+        .chunk_records()
+        .map(|r| match r.unwrap() {
+            ChunkRecord::IndexData(data) => data.conn_id,
+            _ => panic!("expected index data"),
+        })
+        .collect();
+    assert_eq!(conns, [0, 1]);
+}
+
+#[test]
+fn message_types() {
+    let conn = |id, topic: &str, tp: &str, md5: &[u8]| {
+        let fields = [
+            ("topic", topic.as_bytes()),
+            ("type", tp.as_bytes()),
+            ("md5sum", md5),
+            ("message_definition", &b""[..]),
+        ];
+        common::connection_with_fields(id, topic, &fields)
+    };
+    let md5_a = b"992ce8a1687cec8c8bd883ec73ca41d1";
+    let md5_b = b"6a62c6daae103f4ff57a132d6f95cec2";
+    let index = [
+        conn(0, "/chatter", "std_msgs/String", md5_a),
+        conn(1, "/imu", "sensor_msgs/Imu", md5_b),
+        conn(2, "/chatter2", "std_msgs/String", md5_a),
+        conn(3, "/old", "std_msgs/String", md5_b),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("message-types", &[], &index, 4, 0)).unwrap();
+    let types: Vec<_> = bag
+        .message_types()
+        .unwrap()
+        .into_iter()
+        .map(|(tp, md5)| (tp, md5[0]))
+        .collect();
+    let expected = [
+        ("sensor_msgs/Imu".to_string(), 0x6a),
+        ("std_msgs/String".to_string(), 0x6a),
+        ("std_msgs/String".to_string(), 0x99),
+    ];
+    assert_eq!(types, expected);
+    assert_eq!(bag.message_type_conflicts().unwrap(), ["std_msgs/String"]);
+
+    let index = [conn(0, "/chatter", "std_msgs/String", md5_a)].concat();
+    let bag = RosBag::new(common::bag("message-types-ok", &[], &index, 1, 0)).unwrap();
+    assert!(bag.message_type_conflicts().unwrap().is_empty());
+}
+
+#[test]
+fn invalid_md5() {
+    let conn = |md5: &[u8]| {
+        let fields = [
+            ("topic", &b"/chatter"[..]),
+            ("type", b"std_msgs/String"),
+            ("md5sum", md5),
+            ("message_definition", b""),
+        ];
+        common::connection_with_fields(0, "/chatter", &fields)
+    };
+    let read = |md5: &[u8]| {
+        let buf = common::bag_bytes(&[], &conn(md5), 1, 0);
+        let bag = common::open(&buf).unwrap();
+        let res = bag.index_records().next().unwrap();
+        res.map(|_| ())
+    };
+    assert!(read(b"992ce8a1687cec8c8bd883ec73ca41d1").is_ok());
+    let short = read(b"992ce8a1687cec8c").unwrap_err();
+    assert!(matches!(
+        short,
+        Error::InvalidMd5 { topic: Some(ref t), reason: "expected 32 hex characters" } if t == "/chatter"
+    ));
+    let upper = read(b"992CE8A1687CEC8C8BD883EC73CA41D1").unwrap_err();
+    assert!(matches!(
+        upper,
+        Error::InvalidMd5 {
+            reason: "invalid lowercase hex encoding",
+            ..
+        }
+    ));
+    assert_eq!(
+        upper.to_string(),
+        "rosbag::Error: invalid md5sum of connection on /chatter: invalid lowercase hex encoding"
+    );
+}
+
+#[test]
+fn caller_ids() {
+    let conn = |id, topic: &str, caller_id: &[u8]| {
+        let mut fields = vec![
+            ("topic", topic.as_bytes()),
+            ("type", &b"std_msgs/String"[..]),
+            ("md5sum", b"992ce8a1687cec8c8bd883ec73ca41d1"),
+            ("message_definition", b""),
+        ];
+        if !caller_id.is_empty() {
+            fields.push(("callerid", caller_id));
+        }
+        common::connection_with_fields(id, topic, &fields)
+    };
+    let index = [
+        conn(0, "/chatter", b"/talker"),
+        conn(1, "/imu", b"/driver"),
+        conn(2, "/chatter2", b"/talker"),
+        conn(3, "/anon", b""),
+    ]
+    .concat();
+    let bag = RosBag::new(common::bag("caller-ids", &[], &index, 4, 0)).unwrap();
+    assert_eq!(bag.caller_ids().unwrap(), ["/driver", "/talker"]);
+}
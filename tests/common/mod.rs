@@ -0,0 +1,192 @@
+//! Helpers for building ROS bag files in tests.
+#![allow(dead_code)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const VERSION: &[u8] = b"#ROSBAG V2.0\n";
+
+pub fn header(fields: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (name, val) in fields {
+        let len = (name.len() + 1 + val.len()) as u32;
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(b'=');
+        buf.extend_from_slice(val);
+    }
+    buf
+}
+
+pub fn record(fields: &[(&str, &[u8])], data: &[u8]) -> Vec<u8> {
+    let header = header(fields);
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&header);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+pub fn time(t: u64) -> [u8; 8] {
+    let mut buf = [0; 8];
+    buf[..4].copy_from_slice(&((t / 1_000_000_000) as u32).to_le_bytes());
+    buf[4..].copy_from_slice(&((t % 1_000_000_000) as u32).to_le_bytes());
+    buf
+}
+
+pub fn connection(id: u32, topic: &str) -> Vec<u8> {
+    connection_with_definition(id, topic, "string data\n")
+}
+
+/// Connection record with custom message definition.
+pub fn connection_with_definition(id: u32, topic: &str, definition: &str) -> Vec<u8> {
+    connection_with_fields(
+        id,
+        topic,
+        &[
+            ("topic", topic.as_bytes()),
+            ("type", b"std_msgs/String"),
+            ("md5sum", b"992ce8a1687cec8c8bd883ec73ca41d1"),
+            ("message_definition", definition.as_bytes()),
+        ],
+    )
+}
+
+/// Connection record stored under `topic` with custom connection header
+/// `fields` in its data.
+pub fn connection_with_fields(id: u32, topic: &str, fields: &[(&str, &[u8])]) -> Vec<u8> {
+    record(
+        &[
+            ("op", &[0x07]),
+            ("conn", &id.to_le_bytes()),
+            ("topic", topic.as_bytes()),
+        ],
+        &header(fields),
+    )
+}
+
+pub fn message(conn: u32, t: u64, data: &[u8]) -> Vec<u8> {
+    record(
+        &[
+            ("op", &[0x02]),
+            ("conn", &conn.to_le_bytes()),
+            ("time", &time(t)),
+        ],
+        data,
+    )
+}
+
+pub fn chunk(data: &[u8]) -> Vec<u8> {
+    record(
+        &[
+            ("op", &[0x05]),
+            ("compression", b"none"),
+            ("size", &(data.len() as u32).to_le_bytes()),
+        ],
+        data,
+    )
+}
+
+pub fn index_data(conn: u32, entries: &[(u64, u32)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (t, offset) in entries {
+        data.extend_from_slice(&time(*t));
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    record(
+        &[
+            ("op", &[0x04]),
+            ("ver", &1u32.to_le_bytes()),
+            ("conn", &conn.to_le_bytes()),
+            ("count", &(entries.len() as u32).to_le_bytes()),
+        ],
+        &data,
+    )
+}
+
+pub fn chunk_info(chunk_pos: u64, start: u64, end: u64, counts: &[(u32, u32)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (conn, count) in counts {
+        data.extend_from_slice(&conn.to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+    }
+    record(
+        &[
+            ("op", &[0x06]),
+            ("ver", &1u32.to_le_bytes()),
+            ("chunk_pos", &chunk_pos.to_le_bytes()),
+            ("start_time", &time(start)),
+            ("end_time", &time(end)),
+            ("count", &(counts.len() as u32).to_le_bytes()),
+        ],
+        &data,
+    )
+}
+
+/// Position of the first record after the bag header written by `bag`.
+pub fn chunk_section_start(conn_count: u32, chunk_count: u32) -> u64 {
+    (VERSION.len() + bag_header(0, conn_count, chunk_count).len()) as u64
+}
+
+pub fn bag_header(index_pos: u64, conn_count: u32, chunk_count: u32) -> Vec<u8> {
+    record(
+        &[
+            ("op", &[0x03]),
+            ("index_pos", &index_pos.to_le_bytes()),
+            ("conn_count", &conn_count.to_le_bytes()),
+            ("chunk_count", &chunk_count.to_le_bytes()),
+        ],
+        &[],
+    )
+}
+
+/// Assemble bag file from the chunk and index sections.
+pub fn bag_bytes(chunks: &[u8], index: &[u8], conn_count: u32, chunk_count: u32) -> Vec<u8> {
+    let index_pos = chunk_section_start(conn_count, chunk_count) + chunks.len() as u64;
+    let mut buf = VERSION.to_vec();
+    buf.extend_from_slice(&bag_header(index_pos, conn_count, chunk_count));
+    buf.extend_from_slice(chunks);
+    buf.extend_from_slice(index);
+    buf
+}
+
+/// Temporary bag file which gets removed on drop.
+pub struct TempBag(PathBuf);
+
+impl AsRef<Path> for TempBag {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempBag {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Assemble bag file from the chunk and index sections and write it into
+/// a temporary file with a name unique for this process.
+pub fn bag(name: &str, chunks: &[u8], index: &[u8], conn_count: u32, chunk_count: u32) -> TempBag {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let file_name = format!("rosbag-test-{}-{}-{}.bag", std::process::id(), n, name);
+    let path = TempBag(std::env::temp_dir().join(file_name));
+    std::fs::write(&path, bag_bytes(chunks, index, conn_count, chunk_count)).unwrap();
+    path
+}
+
+/// Open bag file stored in `buf` without writing it to disk.
+pub fn open(buf: &[u8]) -> std::io::Result<rosbag::RosBag> {
+    rosbag::RosBag::from_reader(buf, buf.len() as u64)
+}
+
+/// Get the first chunk stored in the chunk section of `bag`.
+pub fn first_chunk(bag: &rosbag::RosBag) -> rosbag::record_types::Chunk<'_> {
+    bag.chunk_records()
+        .find_map(|r| match r.unwrap() {
+            rosbag::ChunkRecord::Chunk(chunk) => Some(chunk),
+            _ => None,
+        })
+        .expect("bag contains a chunk")
+}